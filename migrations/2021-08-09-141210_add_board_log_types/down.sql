@@ -0,0 +1,2 @@
+DELETE FROM logs WHERE log_type_id in (11, 12, 13, 14);
+DELETE FROM log_types WHERE id in (11, 12, 13, 14);
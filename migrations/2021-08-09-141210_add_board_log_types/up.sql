@@ -0,0 +1,4 @@
+INSERT INTO log_types (id, name) VALUES (11, "CREATE_BOARD"),
+                                        (12, "RENAME_BOARD"),
+                                        (13, "DEACTIVATE_BOARD"),
+                                        (14, "REACTIVATE_BOARD");
@@ -0,0 +1,15 @@
+ALTER TABLE comments ADD COLUMN edited_at TIMESTAMP NULL DEFAULT NULL AFTER is_hidden;
+
+CREATE TABLE comment_revisions (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    comment_id INT NOT NULL,
+    content MEDIUMTEXT NOT NULL,
+    user_id INT NULL,
+    user_name VARCHAR(100) NULL,
+    user_ip VARBINARY(16) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    FOREIGN KEY (comment_id) REFERENCES comments(id) ON UPDATE CASCADE,
+    INDEX (user_id),
+    INDEX (user_ip),
+    INDEX (created_at)
+);
@@ -0,0 +1,2 @@
+INSERT INTO log_types (id, name) VALUES (15, "RENAME_TOPIC"),
+                                        (16, "MOVE_TOPIC");
@@ -0,0 +1,22 @@
+CREATE TABLE permission_grants (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    permission VARCHAR(100) NOT NULL,
+    wiki_group VARCHAR(100) NULL,
+    wiki_right VARCHAR(100) NULL,
+    board_id INT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    FOREIGN KEY (board_id) REFERENCES boards(id) ON UPDATE CASCADE,
+    INDEX (wiki_group),
+    INDEX (wiki_right)
+);
+
+INSERT INTO permission_grants (permission, wiki_group) VALUES ("close_topic", "boardmanager"),
+                                                              ("suspend_topic", "boardmanager"),
+                                                              ("hide_topic", "boardmanager"),
+                                                              ("pin_topic", "boardmanager"),
+                                                              ("edit_topic", "boardmanager"),
+                                                              ("hide_comment", "boardmanager"),
+                                                              ("edit_comment", "boardmanager"),
+                                                              ("view_hidden", "boardmanager"),
+                                                              ("view_ip", "boardmanager"),
+                                                              ("manage_boards", "boardmanager");
@@ -0,0 +1 @@
+DELETE FROM permission_grants WHERE permission = "manage_users";
@@ -0,0 +1 @@
+INSERT INTO permission_grants (permission, wiki_group) VALUES ("manage_users", "boardmanager");
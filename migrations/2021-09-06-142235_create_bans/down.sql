@@ -0,0 +1,4 @@
+DELETE FROM permission_grants WHERE permission = "manage_bans";
+DELETE FROM logs WHERE log_type_id in (17, 18);
+DELETE FROM log_types WHERE id in (17, 18);
+DROP TABLE bans;
@@ -0,0 +1,22 @@
+CREATE TABLE bans (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    ip_start VARBINARY(16) NULL,
+    ip_end VARBINARY(16) NULL,
+    prefix_length INT NULL,
+    user_id INT NULL,
+    reason VARCHAR(1000) NOT NULL,
+    expires_at TIMESTAMP NULL DEFAULT NULL,
+    author_id INT NULL,
+    author_name VARCHAR(100) NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    INDEX (ip_start),
+    INDEX (ip_end),
+    INDEX (user_id),
+    INDEX (expires_at),
+    INDEX (created_at)
+);
+
+INSERT INTO log_types (id, name) VALUES (17, "BAN"),
+                                        (18, "UNBAN");
+
+INSERT INTO permission_grants (permission, wiki_group) VALUES ("manage_bans", "boardmanager");
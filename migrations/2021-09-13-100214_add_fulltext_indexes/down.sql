@@ -0,0 +1,2 @@
+ALTER TABLE comments DROP INDEX fulltext_content;
+ALTER TABLE topics DROP INDEX fulltext_title;
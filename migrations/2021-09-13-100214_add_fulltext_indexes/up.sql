@@ -0,0 +1,3 @@
+-- The ngram parser tokenizes text without relying on spaces, which Korean needs
+ALTER TABLE topics ADD FULLTEXT INDEX fulltext_title (title) WITH PARSER ngram;
+ALTER TABLE comments ADD FULLTEXT INDEX fulltext_content (content) WITH PARSER ngram;
@@ -0,0 +1,2 @@
+ALTER TABLE comments DROP FOREIGN KEY comments_parent_id_fk;
+ALTER TABLE comments DROP COLUMN parent_id;
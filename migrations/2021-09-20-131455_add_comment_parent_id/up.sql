@@ -0,0 +1,3 @@
+ALTER TABLE comments
+    ADD COLUMN parent_id INT NULL DEFAULT NULL AFTER topic_id,
+    ADD CONSTRAINT comments_parent_id_fk FOREIGN KEY (parent_id) REFERENCES comments(id);
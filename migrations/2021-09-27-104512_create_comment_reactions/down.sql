@@ -0,0 +1,4 @@
+DELETE FROM logs WHERE log_type_id = 19;
+DELETE FROM log_types WHERE id = 19;
+DROP TABLE comment_reactions;
+ALTER TABLE boards DROP COLUMN allow_anonymous_reactions;
@@ -0,0 +1,18 @@
+ALTER TABLE boards ADD COLUMN allow_anonymous_reactions BOOLEAN NOT NULL DEFAULT false AFTER is_active;
+
+CREATE TABLE comment_reactions (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    comment_id INT NOT NULL,
+    -- Binary collation, as the general ones consider every emoji equal
+    emoji VARCHAR(32) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+    user_id INT NULL,
+    user_ip VARBINARY(16) NOT NULL,
+    -- Anonymous reactions are deduplicated by IP, logged-in ones by user_id
+    anonymous_ip VARBINARY(16) AS (IF(user_id IS NULL, user_ip, NULL)) STORED,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    FOREIGN KEY (comment_id) REFERENCES comments(id) ON UPDATE CASCADE,
+    UNIQUE INDEX (comment_id, emoji, user_id),
+    UNIQUE INDEX (comment_id, emoji, anonymous_ip)
+);
+
+INSERT INTO log_types (id, name) VALUES (19, "CONFIGURE_BOARD");
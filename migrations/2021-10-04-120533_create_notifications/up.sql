@@ -0,0 +1,23 @@
+CREATE TABLE topic_watches (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    topic_id INT NOT NULL,
+    user_id INT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    FOREIGN KEY (topic_id) REFERENCES topics(id) ON UPDATE CASCADE,
+    UNIQUE INDEX (topic_id, user_id),
+    INDEX (user_id)
+);
+
+CREATE TABLE notifications (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    user_id INT NOT NULL,
+    kind VARCHAR(32) NOT NULL,
+    topic_id INT NOT NULL,
+    comment_id INT NOT NULL,
+    is_read BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    FOREIGN KEY (topic_id) REFERENCES topics(id) ON UPDATE CASCADE,
+    FOREIGN KEY (comment_id) REFERENCES comments(id) ON UPDATE CASCADE,
+    INDEX (user_id, is_read),
+    INDEX (created_at)
+);
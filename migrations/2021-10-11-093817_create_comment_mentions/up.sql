@@ -0,0 +1,11 @@
+CREATE TABLE comment_mentions (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    comment_id INT NOT NULL,
+    user_id INT NOT NULL,
+    user_name VARCHAR(100) NOT NULL,
+    -- Offsets of the mention in the content, in characters
+    start_offset INT NOT NULL,
+    end_offset INT NOT NULL,
+    FOREIGN KEY (comment_id) REFERENCES comments(id) ON UPDATE CASCADE,
+    INDEX (user_id)
+);
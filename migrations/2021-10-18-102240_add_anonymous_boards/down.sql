@@ -0,0 +1,3 @@
+ALTER TABLE comments DROP COLUMN anonymous_name;
+ALTER TABLE topics DROP COLUMN anonymous_name;
+ALTER TABLE boards DROP COLUMN is_anonymous;
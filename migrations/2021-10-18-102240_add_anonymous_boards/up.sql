@@ -0,0 +1,6 @@
+ALTER TABLE boards ADD COLUMN is_anonymous BOOLEAN NOT NULL DEFAULT false AFTER allow_anonymous_reactions;
+ALTER TABLE topics ADD COLUMN anonymous_name VARCHAR(16) NULL DEFAULT NULL AFTER author_ip;
+ALTER TABLE comments ADD COLUMN anonymous_name VARCHAR(16) NULL DEFAULT NULL AFTER author_ip;
+
+UPDATE boards SET is_anonymous = true WHERE name = 'anon';
+-- Existing posts get their pseudonyms from PSEUDONYM_SECRET at startup.
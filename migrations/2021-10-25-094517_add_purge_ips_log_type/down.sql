@@ -0,0 +1,2 @@
+DELETE FROM logs WHERE log_type_id = 20;
+DELETE FROM log_types WHERE id = 20;
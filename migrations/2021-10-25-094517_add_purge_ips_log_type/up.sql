@@ -0,0 +1 @@
+INSERT INTO log_types (id, name) VALUES (20, "PURGE_IPS");
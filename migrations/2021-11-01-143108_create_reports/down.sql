@@ -0,0 +1,4 @@
+DELETE FROM permission_grants WHERE permission = "manage_reports";
+DELETE FROM logs WHERE log_type_id in (21, 22);
+DELETE FROM log_types WHERE id in (21, 22);
+DROP TABLE reports;
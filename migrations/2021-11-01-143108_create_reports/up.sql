@@ -0,0 +1,26 @@
+CREATE TABLE reports (
+    id INT PRIMARY KEY AUTO_INCREMENT,
+    target_type VARCHAR(16) NOT NULL,
+    target_id INT NOT NULL,
+    reason VARCHAR(32) NOT NULL,
+    content VARCHAR(1000) NOT NULL DEFAULT '',
+    user_id INT NULL,
+    user_name VARCHAR(100) NULL,
+    user_ip VARBINARY(16) NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'open',
+    resolved_by INT NULL,
+    resolved_at TIMESTAMP NULL DEFAULT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    -- Each reporter has one open report on a target at most,
+    -- logged-in users by user_id and anonymous ones by IP
+    open_user_id INT AS (IF(status = 'open', user_id, NULL)) STORED,
+    open_anonymous_ip VARBINARY(16) AS (IF(status = 'open' AND user_id IS NULL, user_ip, NULL)) STORED,
+    INDEX (status, target_type, target_id),
+    UNIQUE INDEX (target_type, target_id, open_user_id),
+    UNIQUE INDEX (target_type, target_id, open_anonymous_ip)
+);
+
+INSERT INTO log_types (id, name) VALUES (21, "RESOLVE_REPORT"),
+                                        (22, "DISMISS_REPORT");
+
+INSERT INTO permission_grants (permission, wiki_group) VALUES ("manage_reports", "boardmanager");
@@ -0,0 +1,2 @@
+DELETE FROM logs WHERE log_type_id IN (23, 24);
+DELETE FROM log_types WHERE id IN (23, 24);
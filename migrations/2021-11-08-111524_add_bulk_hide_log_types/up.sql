@@ -0,0 +1 @@
+INSERT INTO log_types (id, name) VALUES (23, "BULK_HIDE"), (24, "BULK_UNHIDE");
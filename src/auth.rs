@@ -1,7 +1,7 @@
-use actix_web::{
-    client::Client, dev, error::ErrorUnauthorized, web::Bytes, Error, FromRequest, HttpMessage,
-    HttpRequest,
-};
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::profile_cache::{Lookup, PROFILE_CACHE};
+use crate::wiki::rest_url;
+use actix_web::{client::Client, dev, web::Bytes, Error, FromRequest, HttpMessage, HttpRequest};
 use anyhow::anyhow;
 use chrono::prelude::*;
 use derive_more::{Display, Error};
@@ -79,11 +79,15 @@ impl FromRequest for UserInfo {
                         id: Some(id),
                         token: Some(token.to_owned()),
                     }),
-                    Err(_) => err(ErrorUnauthorized("TokenExpired")),
+                    Err(_) => err(CustomError::from(ErrorCode::TokenInvalid).into()),
                 },
                 Err(e) => match e {
-                    DecodeError::TokenExpired => err(ErrorUnauthorized("TokenExpired")),
-                    DecodeError::TokenInvalid => err(ErrorUnauthorized("TokenInvalid")),
+                    DecodeError::TokenExpired => {
+                        err(CustomError::from(ErrorCode::TokenExpired).into())
+                    }
+                    DecodeError::TokenInvalid => {
+                        err(CustomError::from(ErrorCode::TokenInvalid).into())
+                    }
                 },
             }
         } else {
@@ -110,12 +114,12 @@ impl FromRequest for RefreshToken {
                 refresh_token: token_cookie.value().to_owned(),
             })
         } else {
-            err(ErrorUnauthorized("Token is missing"))
+            err(CustomError::from(ErrorCode::TokenMissing).into())
         }
     }
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Profile {
     pub id: i32,
     pub username: String,
@@ -126,12 +130,6 @@ pub struct Profile {
     pub email: String,
 }
 
-impl Profile {
-    pub fn is_admin(&self) -> bool {
-        self.groups.contains(&"boardmanager".to_owned())
-    }
-}
-
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 struct MwProfileResponse {
     sub: i32,
@@ -148,10 +146,37 @@ struct MwProfileResponse {
 }
 
 impl Profile {
+    /// Returns the profile of the token owner, from the cache if possible.
+    /// A stale cached profile is returned as is and refreshed in the background.
     pub async fn get(token: &str) -> anyhow::Result<Self> {
+        let claims = decode(token).map_err(|e| anyhow!(format!("{}", e)))?;
+        let user_id = claims.sub.parse::<i32>()?;
+        match PROFILE_CACHE.lookup(user_id) {
+            Lookup::Fresh(profile) => Ok(profile),
+            Lookup::Stale(profile) => {
+                if PROFILE_CACHE.begin_refresh(user_id) {
+                    let token = token.to_owned();
+                    actix_web::rt::spawn(async move {
+                        match Self::fetch(&token).await {
+                            Ok(profile) => PROFILE_CACHE.insert(profile, claims.exp),
+                            Err(_) => PROFILE_CACHE.cancel_refresh(user_id),
+                        }
+                    });
+                }
+                Ok(profile)
+            }
+            Lookup::Missing => {
+                let profile = Self::fetch(token).await?;
+                PROFILE_CACHE.insert(profile.clone(), claims.exp);
+                Ok(profile)
+            }
+        }
+    }
+
+    async fn fetch(token: &str) -> anyhow::Result<Self> {
         let client = Client::default();
         let mut res = client
-            .get("https://librewiki.net/rest.php/oauth2/resource/profile")
+            .get(rest_url("/oauth2/resource/profile"))
             .set_header("Accept", "application/json")
             .set_header("Authorization", format!("Bearer {}", token))
             .send()
@@ -1,4 +1,5 @@
-use actix_web::{dev, error::ErrorBadRequest, Error, FromRequest, HttpRequest};
+use crate::custom_error::{CustomError, ErrorCode};
+use actix_web::{dev, Error, FromRequest, HttpRequest};
 use futures::future::{err, ok, Ready};
 use std::{net::IpAddr, str::FromStr};
 
@@ -17,7 +18,9 @@ impl FromRequest for ConnectionInfo {
             Some(remote) => ok(ConnectionInfo {
                 ip: IpAddr::from_str(remote.split(':').collect::<Vec<&str>>()[0]).unwrap(),
             }),
-            None => err(ErrorBadRequest("No IP address")),
+            None => err(CustomError::from(ErrorCode::InvalidRequest)
+                .with_message("No IP address")
+                .into()),
         }
     }
 }
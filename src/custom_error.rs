@@ -1,40 +1,284 @@
-use actix_web::{dev::HttpResponseBuilder, error, http::header, http::StatusCode, HttpResponse};
-use derive_more::{Display, Error};
+use actix_web::{
+    error::{self, BlockingError, PathError, QueryPayloadError},
+    http::StatusCode,
+    HttpRequest, HttpResponse,
+};
+use std::fmt;
 
-#[derive(Debug, Display, Error)]
+/// Stable machine-readable codes for every error the API returns.
+/// Clients should match on these instead of the message.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidRequest,
+    ValidationFailed,
+    NothingToChange,
+    EmptyQuery,
+    InvalidCidr,
+    BanTargetMissing,
+    ModerationTargetMissing,
+    InvalidParent,
+    InvalidReaction,
+    TokenMissing,
+    TokenExpired,
+    TokenInvalid,
+    PermissionDenied,
+    UserBlocked,
+    BoardInactive,
+    TopicHidden,
+    TopicClosed,
+    TopicSuspended,
+    CommentHidden,
+    BoardNotFound,
+    TopicNotFound,
+    CommentNotFound,
+    BanNotFound,
+    ReportNotFound,
+    BoardNameTaken,
+    AlreadyReported,
+    RateLimited,
+    InternalError,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest
+            | ErrorCode::ValidationFailed
+            | ErrorCode::NothingToChange
+            | ErrorCode::EmptyQuery
+            | ErrorCode::InvalidCidr
+            | ErrorCode::BanTargetMissing
+            | ErrorCode::ModerationTargetMissing
+            | ErrorCode::InvalidParent
+            | ErrorCode::InvalidReaction => StatusCode::BAD_REQUEST,
+            ErrorCode::TokenMissing | ErrorCode::TokenExpired | ErrorCode::TokenInvalid => {
+                StatusCode::UNAUTHORIZED
+            }
+            ErrorCode::PermissionDenied
+            | ErrorCode::UserBlocked
+            | ErrorCode::BoardInactive
+            | ErrorCode::TopicHidden
+            | ErrorCode::TopicClosed
+            | ErrorCode::TopicSuspended
+            | ErrorCode::CommentHidden => StatusCode::FORBIDDEN,
+            ErrorCode::BoardNotFound
+            | ErrorCode::TopicNotFound
+            | ErrorCode::CommentNotFound
+            | ErrorCode::BanNotFound
+            | ErrorCode::ReportNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::BoardNameTaken | ErrorCode::AlreadyReported => StatusCode::CONFLICT,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn message(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "Request is invalid",
+            ErrorCode::ValidationFailed => "Validation failed",
+            ErrorCode::NothingToChange => "Nothing to change",
+            ErrorCode::EmptyQuery => "Query is empty",
+            ErrorCode::InvalidCidr => "Invalid cidr",
+            ErrorCode::BanTargetMissing => "Either cidr or user_id is required",
+            ErrorCode::ModerationTargetMissing => "Either cidr or author_id is required",
+            ErrorCode::InvalidParent => "Parent comment is not in this topic",
+            ErrorCode::InvalidReaction => "Reaction is not allowed",
+            ErrorCode::TokenMissing => "Token is missing",
+            ErrorCode::TokenExpired => "Token is expired",
+            ErrorCode::TokenInvalid => "Token is invalid",
+            ErrorCode::PermissionDenied => "Permission denied",
+            ErrorCode::UserBlocked => "You are blocked",
+            ErrorCode::BoardInactive => "Board is inactive",
+            ErrorCode::TopicHidden => "Topic is hidden",
+            ErrorCode::TopicClosed => "Topic is closed",
+            ErrorCode::TopicSuspended => "Topic is suspended",
+            ErrorCode::CommentHidden => "Comment is hidden",
+            ErrorCode::BoardNotFound => "Board is not found",
+            ErrorCode::TopicNotFound => "Topic is not found",
+            ErrorCode::CommentNotFound => "Comment is not found",
+            ErrorCode::BanNotFound => "Ban is not found",
+            ErrorCode::ReportNotFound => "No open reports on the target",
+            ErrorCode::BoardNameTaken => "Board name is already taken",
+            ErrorCode::AlreadyReported => "You already reported this",
+            ErrorCode::RateLimited => "Too many requests",
+            ErrorCode::InternalError => "Internal server error",
+        }
+    }
+}
+
+/// The error type of every handler, serialized as
+/// `{ "code", "status", "message", "details"? }`.
+#[derive(Debug)]
 pub struct CustomError {
-    pub err: anyhow::Error,
+    pub code: ErrorCode,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+    /// The cause of an internal error. It is reported but never shown to clients.
+    pub source: Option<anyhow::Error>,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorBody<'a> {
+    code: ErrorCode,
+    status: u16,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a serde_json::Value>,
+}
+
+impl CustomError {
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl fmt::Display for CustomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{}: {}", self.message, source),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for CustomError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_ref().map(|x| x.as_ref())
+    }
 }
 
 impl error::ResponseError for CustomError {
     fn error_response(&self) -> HttpResponse {
-        HttpResponseBuilder::new(self.status_code())
-            .set_header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-            .body("INTERNAL SERVER ERROR")
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code,
+            status: self.status_code().as_u16(),
+            message: &self.message,
+            details: self.details.as_ref(),
+        })
     }
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        self.code.status()
     }
 }
 
-impl From<r2d2::Error> for CustomError {
-    fn from(err: r2d2::Error) -> CustomError {
+impl From<ErrorCode> for CustomError {
+    fn from(code: ErrorCode) -> CustomError {
         CustomError {
-            err: anyhow::anyhow!(err),
+            code,
+            message: code.message().to_owned(),
+            details: None,
+            source: None,
         }
     }
 }
 
-impl From<actix_web::error::BlockingError<anyhow::Error>> for CustomError {
-    fn from(err: actix_web::error::BlockingError<anyhow::Error>) -> CustomError {
+impl From<anyhow::Error> for CustomError {
+    fn from(err: anyhow::Error) -> CustomError {
         CustomError {
-            err: anyhow::anyhow!(err),
+            source: Some(err),
+            ..ErrorCode::InternalError.into()
         }
     }
 }
 
-impl From<anyhow::Error> for CustomError {
-    fn from(err: anyhow::Error) -> CustomError {
-        CustomError { err }
+impl From<r2d2::Error> for CustomError {
+    fn from(err: r2d2::Error) -> CustomError {
+        anyhow::anyhow!(err).into()
+    }
+}
+
+impl From<diesel::result::Error> for CustomError {
+    fn from(err: diesel::result::Error) -> CustomError {
+        anyhow::anyhow!(err).into()
+    }
+}
+
+impl<E> From<BlockingError<E>> for CustomError
+where
+    E: Into<CustomError> + fmt::Debug,
+{
+    fn from(err: BlockingError<E>) -> CustomError {
+        match err {
+            BlockingError::Error(e) => e.into(),
+            BlockingError::Canceled => anyhow::anyhow!("Thread pool is gone").into(),
+        }
+    }
+}
+
+impl From<actix_web_validator::Error> for CustomError {
+    fn from(err: actix_web_validator::Error) -> CustomError {
+        match err {
+            actix_web_validator::Error::Validate(e) => {
+                CustomError::from(ErrorCode::ValidationFailed)
+                    .with_details(serde_json::to_value(e).unwrap_or_default())
+            }
+            e => CustomError::from(ErrorCode::InvalidRequest).with_message(e.to_string()),
+        }
+    }
+}
+
+/// Error handler for `actix_web_validator::JsonConfig`.
+pub fn json_error_handler(err: actix_web_validator::Error, _: &HttpRequest) -> error::Error {
+    CustomError::from(err).into()
+}
+
+/// Error handler for `web::QueryConfig`.
+pub fn query_error_handler(err: QueryPayloadError, _: &HttpRequest) -> error::Error {
+    CustomError::from(ErrorCode::InvalidRequest)
+        .with_message(err.to_string())
+        .into()
+}
+
+/// Error handler for `web::PathConfig`.
+pub fn path_error_handler(err: PathError, _: &HttpRequest) -> error::Error {
+    CustomError::from(ErrorCode::InvalidRequest)
+        .with_message(err.to_string())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::Body, ResponseError};
+
+    fn body_json(response: &mut HttpResponse) -> serde_json::Value {
+        match response.take_body().as_ref() {
+            Some(Body::Bytes(bytes)) => serde_json::from_slice(bytes).expect("must succeed"),
+            _ => panic!("body must be bytes"),
+        }
+    }
+
+    #[test]
+    fn test_error_response() {
+        let error = CustomError::from(ErrorCode::TopicNotFound);
+        let mut response = error.error_response();
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(
+            serde_json::json!({
+                "code": "topic_not_found",
+                "status": 404,
+                "message": "Topic is not found",
+            }),
+            body_json(&mut response)
+        );
+
+        let error = CustomError::from(anyhow::anyhow!("connection refused"));
+        let mut response = error.error_response();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+        let body = body_json(&mut response);
+        assert_eq!("internal_error", body["code"]);
+        assert_eq!("Internal server error", body["message"]);
+
+        let error = CustomError::from(ErrorCode::RateLimited)
+            .with_details(serde_json::json!({ "retry_after": 30 }));
+        let mut response = error.error_response();
+        assert_eq!(30, body_json(&mut response)["details"]["retry_after"]);
     }
 }
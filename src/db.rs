@@ -1,5 +1,7 @@
+use diesel::dsl::sql;
 use diesel::prelude::*;
 use diesel::r2d2::ConnectionManager;
+use diesel::sql_types::{Bigint, Unsigned};
 use std::env;
 
 pub type DbPool = r2d2::Pool<ConnectionManager<MysqlConnection>>;
@@ -11,6 +13,12 @@ pub fn create_connection_pool() -> r2d2::Pool<ConnectionManager<MysqlConnection>
         .expect("Failed to create pool.")
 }
 
+/// Returns the id generated by the last insert on this connection.
+pub fn last_insert_id(conn: &MysqlConnection) -> anyhow::Result<i32> {
+    let id = diesel::select(sql::<Unsigned<Bigint>>("LAST_INSERT_ID()")).first::<u64>(conn)?;
+    Ok(id as i32)
+}
+
 #[cfg(test)]
 pub fn create_connection() -> MysqlConnection {
     use dotenv::dotenv;
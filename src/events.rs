@@ -0,0 +1,447 @@
+use crate::models::{Comment, Topic};
+use actix_web::{web::Bytes, HttpRequest, HttpResponse};
+use chrono::Utc;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+lazy_static! {
+    pub static ref EVENTS: EventBus = EventBus::new(HISTORY_SIZE, HISTORY_BYTES);
+}
+
+/// Number of recent events kept for clients resuming with `Last-Event-ID`.
+const HISTORY_SIZE: usize = 1000;
+
+/// Total size of the payloads of the kept events, as comments can be long.
+const HISTORY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Comment lines are sent this often so that proxies keep idle streams open
+/// and disconnected clients are noticed.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(20);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventKind {
+    NewTopic,
+    NewComment,
+    TopicStatus,
+    CommentStatus,
+}
+
+impl EventKind {
+    fn name(&self) -> &'static str {
+        match self {
+            EventKind::NewTopic => "new_topic",
+            EventKind::NewComment => "new_comment",
+            EventKind::TopicStatus => "topic_status",
+            EventKind::CommentStatus => "comment_status",
+        }
+    }
+}
+
+/// The events a subscriber receives: those of a board, or of a single topic.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Channel {
+    Board(i32),
+    Topic(i32),
+}
+
+#[derive(Debug)]
+pub struct Event {
+    pub kind: EventKind,
+    pub board_id: i32,
+    pub topic_id: i32,
+    /// Set on comment events.
+    pub comment_id: Option<i32>,
+    /// Whether the topic, or the comment or its topic, is hidden.
+    pub is_hidden: bool,
+    /// Payload for those who may view hidden content.
+    pub data: serde_json::Value,
+    /// Payload for everyone else, or `None` if they must not receive the event.
+    pub public_data: Option<serde_json::Value>,
+}
+
+impl Event {
+    pub fn new_topic(topic: &Topic) -> Self {
+        Event {
+            kind: EventKind::NewTopic,
+            board_id: topic.board_id,
+            topic_id: topic.id,
+            comment_id: None,
+            is_hidden: topic.is_hidden,
+            data: serde_json::to_value(topic.get_public(true)).unwrap_or_default(),
+            public_data: if topic.is_hidden {
+                None
+            } else {
+                serde_json::to_value(topic.get_public(false)).ok()
+            },
+        }
+    }
+
+    /// Others only learn that a hidden topic is gone, not what it is.
+    pub fn topic_status(topic: &Topic) -> Self {
+        Event {
+            kind: EventKind::TopicStatus,
+            board_id: topic.board_id,
+            topic_id: topic.id,
+            comment_id: None,
+            is_hidden: topic.is_hidden,
+            data: serde_json::to_value(topic.get_public(true)).unwrap_or_default(),
+            public_data: if topic.is_hidden {
+                Some(serde_json::json!({
+                    "id": topic.id,
+                    "board_id": topic.board_id,
+                    "is_hidden": true,
+                }))
+            } else {
+                serde_json::to_value(topic.get_public(false)).ok()
+            },
+        }
+    }
+
+    /// Reply counts and reactions are not included.
+    pub fn new_comment(topic: &Topic, comment: &Comment) -> Self {
+        Self::from_comment(EventKind::NewComment, topic, comment)
+    }
+
+    /// Reply counts and reactions are not included.
+    pub fn comment_status(topic: &Topic, comment: &Comment) -> Self {
+        Self::from_comment(EventKind::CommentStatus, topic, comment)
+    }
+
+    fn from_comment(kind: EventKind, topic: &Topic, comment: &Comment) -> Self {
+        Event {
+            kind,
+            board_id: topic.board_id,
+            topic_id: topic.id,
+            comment_id: Some(comment.id),
+            is_hidden: topic.is_hidden || comment.is_hidden,
+            data: serde_json::to_value(comment.get_public(true)).unwrap_or_default(),
+            public_data: if topic.is_hidden {
+                None
+            } else {
+                serde_json::to_value(comment.get_public(false)).ok()
+            },
+        }
+    }
+
+    /// Whether the event hides content that earlier events may have shown.
+    fn hides(&self, earlier: &Event) -> bool {
+        match self.kind {
+            EventKind::TopicStatus => self.is_hidden && earlier.topic_id == self.topic_id,
+            EventKind::CommentStatus => {
+                self.is_hidden
+                    && earlier.comment_id.is_some()
+                    && earlier.comment_id == self.comment_id
+            }
+            _ => false,
+        }
+    }
+
+    /// Approximate memory used by the payloads.
+    fn size(&self) -> usize {
+        self.data.to_string().len() + self.public_data.as_ref().map_or(0, |x| x.to_string().len())
+    }
+
+    fn is_in(&self, channel: Channel) -> bool {
+        match channel {
+            Channel::Board(board_id) => self.board_id == board_id,
+            Channel::Topic(topic_id) => self.topic_id == topic_id,
+        }
+    }
+
+    fn to_bytes(&self, id: &str, show_hidden: bool) -> Option<Bytes> {
+        let data = if show_hidden {
+            Some(&self.data)
+        } else {
+            self.public_data.as_ref()
+        }?;
+        Some(Bytes::from(format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            id,
+            self.kind.name(),
+            data
+        )))
+    }
+}
+
+struct Subscriber {
+    channel: Channel,
+    show_hidden: bool,
+    sender: UnboundedSender<Bytes>,
+}
+
+struct Entry {
+    seq: u64,
+    size: usize,
+    event: Event,
+}
+
+struct Inner {
+    next_seq: u64,
+    history: VecDeque<Entry>,
+    /// Sum of the sizes of the entries in `history`.
+    history_bytes: usize,
+    subscribers: Vec<Subscriber>,
+}
+
+/// Broadcasts events to Server-Sent Events streams.
+///
+/// Event ids are `{epoch}-{seq}`, where `epoch` changes on every restart.
+/// A client resuming from an id that is unknown or too old for the history gets
+/// a `reset` event and should reload what it shows.
+/// Once content is hidden, earlier events showing it are replayed to those with
+/// hidden content permissions only.
+pub struct EventBus {
+    epoch: i64,
+    capacity: usize,
+    max_bytes: usize,
+    inner: Mutex<Inner>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        EventBus {
+            epoch: Utc::now().timestamp_millis(),
+            capacity,
+            max_bytes,
+            inner: Mutex::new(Inner {
+                next_seq: 1,
+                history: VecDeque::with_capacity(capacity),
+                history_bytes: 0,
+                subscribers: Vec::new(),
+            }),
+        }
+    }
+
+    fn event_id(&self, seq: u64) -> String {
+        format!("{}-{}", self.epoch, seq)
+    }
+
+    /// Returns the sequence number of `event_id` if it was issued by this bus.
+    fn parse_event_id(&self, event_id: &str) -> Option<u64> {
+        let mut split = event_id.trim().splitn(2, '-');
+        let epoch = split.next()?.parse::<i64>().ok()?;
+        let seq = split.next()?.parse::<u64>().ok()?;
+        if epoch == self.epoch {
+            Some(seq)
+        } else {
+            None
+        }
+    }
+
+    pub fn publish(&self, event: Event) {
+        let mut inner = self.inner.lock().unwrap();
+        let seq = inner.next_seq;
+        inner.next_seq += 1;
+        let id = self.event_id(seq);
+        inner.subscribers.retain(|x| {
+            if !event.is_in(x.channel) {
+                return !x.sender.is_closed();
+            }
+            match event.to_bytes(&id, x.show_hidden) {
+                Some(bytes) => x.sender.unbounded_send(bytes).is_ok(),
+                None => !x.sender.is_closed(),
+            }
+        });
+        let inner = &mut *inner;
+        for entry in inner.history.iter_mut() {
+            if entry.event.public_data.is_some() && event.hides(&entry.event) {
+                entry.event.public_data = None;
+                inner.history_bytes -= entry.size;
+                entry.size = entry.event.size();
+                inner.history_bytes += entry.size;
+            }
+        }
+        let size = event.size();
+        while inner.history.len() >= self.capacity
+            || (!inner.history.is_empty() && inner.history_bytes + size > self.max_bytes)
+        {
+            if let Some(entry) = inner.history.pop_front() {
+                inner.history_bytes -= entry.size;
+            }
+        }
+        inner.history_bytes += size;
+        inner.history.push_back(Entry { seq, size, event });
+    }
+
+    /// Opens a stream of the events in `channel`.
+    /// Events after `last_event_id` are replayed first if they are still in the history.
+    pub fn subscribe(
+        &self,
+        channel: Channel,
+        show_hidden: bool,
+        last_event_id: Option<&str>,
+    ) -> UnboundedReceiver<Bytes> {
+        let (sender, receiver) = unbounded();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(last_event_id) = last_event_id {
+            let oldest = inner.history.front().map_or(inner.next_seq, |x| x.seq);
+            match self.parse_event_id(last_event_id) {
+                Some(last) if last + 1 >= oldest && last < inner.next_seq => {
+                    for Entry { seq, event, .. } in inner.history.iter().filter(|x| x.seq > last) {
+                        if !event.is_in(channel) {
+                            continue;
+                        }
+                        if let Some(bytes) = event.to_bytes(&self.event_id(*seq), show_hidden) {
+                            let _ = sender.unbounded_send(bytes);
+                        }
+                    }
+                }
+                _ => {
+                    let _ =
+                        sender.unbounded_send(Bytes::from_static(b"event: reset\ndata: {}\n\n"));
+                }
+            }
+        }
+        inner.subscribers.push(Subscriber {
+            channel,
+            show_hidden,
+            sender,
+        });
+        receiver
+    }
+
+    /// Sends a comment line to every subscriber, dropping those that are gone.
+    fn heartbeat(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.subscribers.retain(|x| {
+            x.sender
+                .unbounded_send(Bytes::from_static(b":\n\n"))
+                .is_ok()
+        });
+    }
+
+    /// Spawns the heartbeat on the current arbiter.
+    pub fn start_heartbeat(&'static self) {
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(HEARTBEAT_INTERVAL);
+            loop {
+                interval.tick().await;
+                self.heartbeat();
+            }
+        });
+    }
+}
+
+/// Subscribes to `channel` and streams its events, resuming from the `Last-Event-ID` header.
+pub fn stream_response(request: &HttpRequest, channel: Channel, show_hidden: bool) -> HttpResponse {
+    let last_event_id = request
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|x| x.to_str().ok());
+    let receiver = EVENTS.subscribe(channel, show_hidden, last_event_id);
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .set_header("Cache-Control", "no-cache")
+        .streaming(receiver.map(Ok::<_, actix_web::Error>))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: EventKind, topic_id: i32, public: bool) -> Event {
+        Event {
+            kind,
+            board_id: 1,
+            topic_id,
+            comment_id: None,
+            is_hidden: !public,
+            data: serde_json::json!({ "id": topic_id }),
+            public_data: if public {
+                Some(serde_json::json!({ "id": topic_id }))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn received(receiver: &mut UnboundedReceiver<Bytes>) -> Vec<String> {
+        let mut messages = Vec::new();
+        while let Ok(Some(bytes)) = receiver.try_next() {
+            messages.push(String::from_utf8(bytes.to_vec()).expect("must succeed"));
+        }
+        messages
+    }
+
+    #[test]
+    fn test_event_bus() {
+        let bus = EventBus::new(2, 1024);
+        let mut board = bus.subscribe(Channel::Board(1), false, None);
+        let mut topic = bus.subscribe(Channel::Topic(2), true, None);
+        bus.publish(event(EventKind::NewTopic, 2, true));
+        bus.publish(event(EventKind::NewComment, 3, true));
+        bus.publish(event(EventKind::TopicStatus, 2, false));
+
+        let first = bus.event_id(1);
+        assert_eq!(
+            vec![
+                format!("id: {}\nevent: new_topic\ndata: {{\"id\":2}}\n\n", first),
+                format!(
+                    "id: {}\nevent: new_comment\ndata: {{\"id\":3}}\n\n",
+                    bus.event_id(2)
+                ),
+            ],
+            received(&mut board)
+        );
+        assert_eq!(2, received(&mut topic).len());
+
+        // Only the last two events are kept
+        let mut resumed = bus.subscribe(Channel::Board(1), true, Some(&bus.event_id(1)));
+        assert_eq!(2, received(&mut resumed).len());
+        let mut resumed = bus.subscribe(Channel::Board(1), true, Some(&bus.event_id(2)));
+        assert_eq!(1, received(&mut resumed).len());
+        let mut resumed = bus.subscribe(Channel::Board(1), true, Some(&bus.event_id(3)));
+        assert_eq!(0, received(&mut resumed).len());
+        let mut reset = bus.subscribe(Channel::Board(1), true, Some("0-1"));
+        assert_eq!(
+            vec!["event: reset\ndata: {}\n\n".to_owned()],
+            received(&mut reset)
+        );
+
+        drop(board);
+        bus.heartbeat();
+        assert_eq!(5, bus.inner.lock().unwrap().subscribers.len());
+        futures::executor::block_on(async {
+            assert_eq!(Some(Bytes::from_static(b":\n\n")), topic.next().await);
+        });
+    }
+    #[test]
+    fn test_hidden_history() {
+        let bus = EventBus::new(10, 1024);
+        bus.publish(event(EventKind::NewTopic, 2, true));
+        let mut comment = event(EventKind::NewComment, 2, true);
+        comment.comment_id = Some(5);
+        bus.publish(comment);
+        bus.publish(event(EventKind::NewTopic, 3, true));
+
+        let mut status = event(EventKind::CommentStatus, 2, true);
+        status.comment_id = Some(5);
+        status.is_hidden = true;
+        bus.publish(status);
+        // The comment is left out, but not the other topic
+        let mut resumed = bus.subscribe(Channel::Board(1), false, Some(&bus.event_id(1)));
+        assert_eq!(2, received(&mut resumed).len());
+
+        bus.publish(event(EventKind::TopicStatus, 2, false));
+        let mut resumed = bus.subscribe(Channel::Board(1), false, Some(&bus.event_id(0)));
+        assert_eq!(1, received(&mut resumed).len());
+        let mut resumed = bus.subscribe(Channel::Board(1), true, Some(&bus.event_id(0)));
+        assert_eq!(5, received(&mut resumed).len());
+    }
+
+    #[test]
+    fn test_history_bytes() {
+        let bus = EventBus::new(10, 40);
+        // Each event takes 18 bytes
+        bus.publish(event(EventKind::NewTopic, 10, true));
+        bus.publish(event(EventKind::NewTopic, 11, true));
+        bus.publish(event(EventKind::NewTopic, 12, true));
+        let inner = bus.inner.lock().unwrap();
+        assert_eq!(2, inner.history.len());
+        assert_eq!(36, inner.history_bytes);
+    }
+}
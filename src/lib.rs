@@ -6,10 +6,16 @@ pub mod auth;
 pub mod connection_info;
 pub mod custom_error;
 pub mod db;
+pub mod events;
+pub mod markdown;
 pub mod models;
+pub mod profile_cache;
+pub mod rate_limit;
+pub mod retention;
 pub mod routes;
 pub mod s3;
 pub mod schema;
+pub mod wiki;
 use actix_cors::Cors;
 use actix_web::{
     middleware::{DefaultHeaders, Logger},
@@ -32,7 +38,19 @@ pub async fn run() -> std::io::Result<()> {
         },
     ));
     env::set_var("RUST_BACKTRACE", "1");
+    // Settings and secrets are read now, so that a misconfiguration fails at startup
+    lazy_static::initialize(&models::PSEUDONYM_KEY);
+    if *models::IP_DISPLAY == models::IpDisplay::Hashed {
+        lazy_static::initialize(&models::IP_HASH_KEY);
+    }
+    lazy_static::initialize(&rate_limit::RATE_LIMITER);
+    lazy_static::initialize(&retention::IP_RETENTION_DAYS);
     let pool = db::create_connection_pool();
+    models::Board::fill_all_pseudonyms(&pool.get().expect("Failed to get a connection"))
+        .expect("Failed to fill in pseudonyms");
+    events::EVENTS.start_heartbeat();
+    wiki::PAGE_CACHE.start_refresh();
+    retention::start_purge(pool.clone());
     println!("http://{}", env::var("HOST").expect("HOST is not set"));
 
     HttpServer::new(move || {
@@ -54,7 +72,13 @@ pub async fn run() -> std::io::Result<()> {
             .wrap(cors)
             .wrap(DefaultHeaders::new().header("Access-Control-Allow-Credentials", "true"))
             .data(pool.clone())
-            .app_data(actix_web_validator::JsonConfig::default().limit(1024 * 1024 * 1))
+            .app_data(
+                actix_web_validator::JsonConfig::default()
+                    .limit(1024 * 1024 * 1)
+                    .error_handler(custom_error::json_error_handler),
+            )
+            .app_data(web::QueryConfig::default().error_handler(custom_error::query_error_handler))
+            .app_data(web::PathConfig::default().error_handler(custom_error::path_error_handler))
             .service(web::scope("/v1").service(routes::scope()))
             .service(routes::scope())
     })
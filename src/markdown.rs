@@ -0,0 +1,341 @@
+use crate::wiki::{page_url, PAGE_CACHE};
+use ammonia::{Builder, UrlRelative};
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    static ref SANITIZER: Builder<'static> = {
+        let tags = [
+            "p",
+            "br",
+            "hr",
+            "em",
+            "strong",
+            "del",
+            "code",
+            "pre",
+            "blockquote",
+            "ul",
+            "ol",
+            "li",
+            "a",
+            "h1",
+            "h2",
+            "h3",
+            "h4",
+            "h5",
+            "h6",
+            "table",
+            "thead",
+            "tbody",
+            "tr",
+            "th",
+            "td",
+        ];
+        let mut tag_attributes = HashMap::new();
+        tag_attributes.insert("a", ["href", "title"].iter().copied().collect());
+        tag_attributes.insert("ol", ["start"].iter().copied().collect());
+        let mut classes = HashMap::new();
+        // Links to missing wiki pages
+        classes.insert("a", ["new"].iter().copied().collect());
+        let mut builder = Builder::empty();
+        builder
+            .tags(tags.iter().copied().collect())
+            .tag_attributes(tag_attributes)
+            .allowed_classes(classes)
+            .generic_attributes(HashSet::new())
+            .url_schemes(["http", "https", "mailto"].iter().copied().collect())
+            .url_relative(UrlRelative::Deny)
+            .link_rel(Some("nofollow noopener noreferrer"))
+            .clean_content_tags(["script", "style"].iter().copied().collect());
+        builder
+    };
+    pub static ref RENDER_CACHE: RenderCache =
+        RenderCache::new(RENDER_CACHE_SIZE, RENDER_CACHE_BYTES);
+}
+
+/// Number of rendered contents kept in memory.
+const RENDER_CACHE_SIZE: usize = 10000;
+
+/// Total length of the rendered contents kept in memory.
+const RENDER_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Part of a text with wiki links taken out.
+#[derive(PartialEq, Debug)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// `[[title#fragment|label]]`
+    WikiLink {
+        title: &'a str,
+        fragment: Option<&'a str>,
+        label: &'a str,
+    },
+}
+
+fn parse_wiki_link(inner: &str) -> Option<Segment<'_>> {
+    if inner.contains(&['[', ']', '{', '}', '<', '>', '\n'][..]) {
+        return None;
+    }
+    let mut split = inner.splitn(2, '|');
+    let target = split.next()?;
+    let label = split.next().map(str::trim).filter(|x| !x.is_empty());
+    let mut split = target.splitn(2, '#');
+    let title = split.next()?.trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some(Segment::WikiLink {
+        title,
+        fragment: split.next(),
+        label: label.unwrap_or_else(|| target.trim()),
+    })
+}
+
+/// Splits `[[wiki links]]` out of `text`. Malformed links are left as text.
+pub fn split_wiki_links(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    // Start of the text not yet in `segments`
+    let mut plain = 0;
+    let mut pos = 0;
+    while let Some(start) = text[pos..].find("[[").map(|x| pos + x) {
+        let inner = start + 2;
+        let link = text[inner..]
+            .find("]]")
+            .and_then(|end| Some((inner + end, parse_wiki_link(&text[inner..inner + end])?)));
+        match link {
+            Some((end, link)) => {
+                if start > plain {
+                    segments.push(Segment::Text(&text[plain..start]));
+                }
+                segments.push(link);
+                plain = end + 2;
+                pos = plain;
+            }
+            None => pos = inner,
+        }
+    }
+    if plain < text.len() {
+        segments.push(Segment::Text(&text[plain..]));
+    }
+    segments
+}
+
+/// Turns wiki links in `text` into events. Links to pages known to be missing get `class="new"`.
+fn push_wiki_links(events: &mut Vec<Event>, text: &str) {
+    for segment in split_wiki_links(text) {
+        match segment {
+            Segment::Text(text) => events.push(Event::Text(text.to_owned().into())),
+            Segment::WikiLink {
+                title,
+                fragment,
+                label,
+            } => {
+                let class = match PAGE_CACHE.exists(title) {
+                    Some(false) => " class=\"new\"",
+                    _ => "",
+                };
+                events.push(Event::Html(
+                    format!("<a href=\"{}\"{}>", page_url(title, fragment), class).into(),
+                ));
+                events.push(Event::Text(label.to_owned().into()));
+                events.push(Event::Html("</a>".into()));
+            }
+        }
+    }
+}
+
+/// Renders Markdown to HTML, keeping only allowed tags, attributes and link schemes.
+/// Links get `rel="nofollow"`. `[[Page]]` links to a page of the wiki.
+pub fn render(content: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    let mut events = Vec::new();
+    // The parser splits text at brackets, so wiki links are found in runs of text
+    let mut text = String::new();
+    // Wiki links are not made inside code blocks, links and image descriptions
+    let mut literal_depth = 0;
+    for event in Parser::new_ext(content, options) {
+        if let Event::Text(x) = event {
+            text.push_str(&x);
+            continue;
+        }
+        if !text.is_empty() {
+            if literal_depth > 0 {
+                events.push(Event::Text(text.clone().into()));
+            } else {
+                push_wiki_links(&mut events, &text);
+            }
+            text.clear();
+        }
+        match &event {
+            Event::Start(Tag::CodeBlock(_))
+            | Event::Start(Tag::Link(..))
+            | Event::Start(Tag::Image(..)) => literal_depth += 1,
+            Event::End(Tag::CodeBlock(_))
+            | Event::End(Tag::Link(..))
+            | Event::End(Tag::Image(..)) => literal_depth -= 1,
+            _ => {}
+        }
+        events.push(event);
+    }
+    if !text.is_empty() {
+        push_wiki_links(&mut events, &text);
+    }
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events.into_iter());
+    SANITIZER.clean(&unsafe_html).to_string()
+}
+
+struct Entries {
+    html: HashMap<u64, Arc<String>>,
+    /// Keys in insertion order.
+    order: VecDeque<u64>,
+    /// Sum of the lengths of `html`.
+    bytes: usize,
+}
+
+/// Caches rendered HTML by the hash of its source, dropping the oldest entries when full.
+/// HTML longer than the whole cache is not kept.
+pub struct RenderCache {
+    entries: Mutex<Entries>,
+    capacity: usize,
+    max_bytes: usize,
+}
+
+impl RenderCache {
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        RenderCache {
+            entries: Mutex::new(Entries {
+                html: HashMap::new(),
+                order: VecDeque::new(),
+                bytes: 0,
+            }),
+            capacity,
+            max_bytes,
+        }
+    }
+
+    pub fn render(&self, content: &str) -> Arc<String> {
+        let mut s = DefaultHasher::new();
+        content.hash(&mut s);
+        let key = s.finish();
+        if let Some(html) = self.entries.lock().unwrap().html.get(&key) {
+            return html.clone();
+        }
+        // Rendered without the lock, as long contents take a while
+        let html = Arc::new(render(content));
+        if html.len() > self.max_bytes {
+            return html;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.html.insert(key, html.clone()).is_none() {
+            entries.order.push_back(key);
+            entries.bytes += html.len();
+            while entries.order.len() > self.capacity || entries.bytes > self.max_bytes {
+                if let Some(oldest) = entries.order.pop_front() {
+                    if let Some(removed) = entries.html.remove(&oldest) {
+                        entries.bytes -= removed.len();
+                    }
+                }
+            }
+        }
+        html
+    }
+
+    /// Drops every entry, e.g. after a change in how contents are rendered.
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.html.clear();
+        entries.order.clear();
+        entries.bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            "<p><strong>굵게</strong> <del>취소</del></p>\n",
+            render("**굵게** ~~취소~~")
+        );
+        assert_eq!(
+            "<p><a href=\"https://librewiki.net/\" rel=\"nofollow noopener noreferrer\">위키</a></p>\n",
+            render("[위키](https://librewiki.net/)")
+        );
+        assert_eq!(
+            "<p><a rel=\"nofollow noopener noreferrer\">x</a></p>\n",
+            render("[x](javascript:alert(1))")
+        );
+        assert_eq!(
+            "<p>alert(1)</p>",
+            render("<p onclick=\"x\"><script>alert(1)</script>alert(1)</p>")
+        );
+        assert_eq!("<p>&lt;b&gt;</p>\n", render("\\<b\\>"));
+    }
+
+    #[test]
+    fn test_split_wiki_links() {
+        assert_eq!(
+            vec![
+                Segment::Text("[[ ]] "),
+                Segment::WikiLink {
+                    title: "대문",
+                    fragment: None,
+                    label: "대문",
+                },
+                Segment::Text(", "),
+                Segment::WikiLink {
+                    title: "도움말",
+                    fragment: Some("편집"),
+                    label: "편집 방법",
+                },
+                Segment::Text(" [[a<b]]"),
+            ],
+            split_wiki_links("[[ ]] [[대문]], [[도움말#편집|편집 방법]] [[a<b]]")
+        );
+    }
+
+    #[test]
+    fn test_render_wiki_links() {
+        assert_eq!(
+            format!(
+                "<p><a href=\"{}\" rel=\"nofollow noopener noreferrer\">Foo bar</a> <code>[[x]]</code></p>\n",
+                page_url("Foo_bar", None)
+            ),
+            render("[[Foo bar]] `[[x]]`")
+        );
+        assert_eq!("<pre><code>[[x]]</code></pre>\n", render("    [[x]]"));
+    }
+
+    #[test]
+    fn test_render_cache() {
+        let cache = RenderCache::new(1, 1000);
+        let first = cache.render("*a*");
+        assert_eq!("<p><em>a</em></p>\n", first.as_str());
+        assert_eq!(true, Arc::ptr_eq(&first, &cache.render("*a*")));
+        cache.render("*b*");
+        assert_eq!(false, Arc::ptr_eq(&first, &cache.render("*a*")));
+
+        // Each entry takes 18 bytes
+        let cache = RenderCache::new(10, 40);
+        let first = cache.render("*a*");
+        cache.render("*b*");
+        assert_eq!(true, Arc::ptr_eq(&first, &cache.render("*a*")));
+        cache.render("*c*");
+        assert_eq!(false, Arc::ptr_eq(&first, &cache.render("*a*")));
+        assert_eq!(36, cache.entries.lock().unwrap().bytes);
+
+        let cache = RenderCache::new(10, 10);
+        let first = cache.render("*a*");
+        assert_eq!(false, Arc::ptr_eq(&first, &cache.render("*a*")));
+        assert_eq!(0, cache.entries.lock().unwrap().html.len());
+    }
+}
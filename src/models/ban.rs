@@ -0,0 +1,274 @@
+use crate::db::last_insert_id;
+use crate::models::ip_from_bytes;
+use crate::schema::bans;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::dsl::{now, sql};
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Integer};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+pub struct Ban {
+    pub id: i32,
+    pub ip_start: Option<Vec<u8>>,
+    pub ip_end: Option<Vec<u8>>,
+    pub prefix_length: Option<i32>,
+    pub user_id: Option<i32>,
+    pub reason: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub author_id: Option<i32>,
+    pub author_name: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "bans"]
+struct NewBan<'a> {
+    pub ip_start: Option<Vec<u8>>,
+    pub ip_end: Option<Vec<u8>>,
+    pub prefix_length: Option<i32>,
+    pub user_id: Option<i32>,
+    pub reason: &'a str,
+    pub expires_at: Option<NaiveDateTime>,
+    pub author_id: Option<i32>,
+    pub author_name: Option<&'a str>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BanPublic {
+    pub id: i32,
+    pub cidr: Option<String>,
+    pub user_id: Option<i32>,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub author_id: Option<i32>,
+    pub author_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An IP range in the same binary form as `author_ip`:
+/// 4 bytes for IPv4 and 16 bytes for IPv6.
+#[derive(PartialEq, Debug)]
+pub struct IpRange {
+    pub start: Vec<u8>,
+    pub end: Vec<u8>,
+    pub prefix_length: u8,
+}
+
+impl FromStr for IpRange {
+    type Err = anyhow::Error;
+
+    /// Parses a CIDR range such as `192.0.2.0/24` or `2001:db8::/32`.
+    /// A plain address is treated as a range of one address.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut split = s.splitn(2, '/');
+        let ip = IpAddr::from_str(split.next().unwrap_or_default().trim())?;
+        let start: Vec<u8> = match &ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        let max_length = start.len() * 8;
+        let prefix_length = match split.next() {
+            Some(x) => x.trim().parse::<usize>()?,
+            None => max_length,
+        };
+        if prefix_length > max_length {
+            return Err(anyhow!("Prefix length is too long: {}", prefix_length));
+        }
+        let mut start = start;
+        let mut end = start.clone();
+        for i in 0..start.len() {
+            let bits = prefix_length.saturating_sub(i * 8).min(8);
+            let mask = if bits == 0 { 0u8 } else { !0u8 << (8 - bits) };
+            start[i] &= mask;
+            end[i] = start[i] | !mask;
+        }
+        Ok(IpRange {
+            start,
+            end,
+            prefix_length: prefix_length as u8,
+        })
+    }
+}
+
+impl Ban {
+    pub fn create(
+        conn: &MysqlConnection,
+        ip_range: Option<&IpRange>,
+        user_id: Option<i32>,
+        reason: &str,
+        expires_at: Option<NaiveDateTime>,
+        author_id: Option<i32>,
+        author_name: Option<&str>,
+    ) -> Result<Self> {
+        let new_ban = NewBan {
+            ip_start: ip_range.map(|x| x.start.clone()),
+            ip_end: ip_range.map(|x| x.end.clone()),
+            prefix_length: ip_range.map(|x| x.prefix_length.into()),
+            user_id,
+            reason,
+            expires_at,
+            author_id,
+            author_name,
+        };
+        diesel::insert_into(bans::table)
+            .values(new_ban)
+            .execute(conn)?;
+        Self::find_by_id(conn, last_insert_id(conn)?)
+    }
+
+    pub fn get_all(
+        conn: &MysqlConnection,
+        limit: i32,
+        offset: i32,
+        include_expired: bool,
+    ) -> Result<Vec<Self>> {
+        let mut query = bans::table.into_boxed();
+        if !include_expired {
+            query = query.filter(bans::expires_at.is_null().or(bans::expires_at.gt(now)));
+        }
+        let bans = query
+            .order_by(bans::id.desc())
+            .limit(limit.into())
+            .offset(offset.into())
+            .load::<Self>(conn)?;
+        Ok(bans)
+    }
+
+    pub fn find_by_id(conn: &MysqlConnection, id: i32) -> Result<Self> {
+        let ban = bans::table.find(id).first::<Self>(conn)?;
+        Ok(ban)
+    }
+
+    /// Finds an unexpired ban on the user or on a range containing the IP.
+    pub fn find_active(
+        conn: &MysqlConnection,
+        user_id: Option<i32>,
+        ip: &IpAddr,
+    ) -> Result<Option<Self>> {
+        let ip_bin: Vec<u8> = match &ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        let ip_len = ip_bin.len() as i32;
+        let matches_ip = bans::ip_start
+            .le(ip_bin.clone())
+            .and(bans::ip_end.ge(ip_bin))
+            // IPv4 and IPv6 ranges are compared only with addresses of the same length
+            .and(sql::<Bool>("LENGTH(ip_start) = ").bind::<Integer, _>(ip_len));
+        let mut query = bans::table
+            .filter(bans::expires_at.is_null().or(bans::expires_at.gt(now)))
+            .into_boxed();
+        query = match user_id {
+            Some(user_id) => query.filter(matches_ip.or(bans::user_id.eq(user_id))),
+            None => query.filter(matches_ip),
+        };
+        let ban = query.first::<Self>(conn).optional()?;
+        Ok(ban)
+    }
+
+    /// Lifts the ban by expiring it now. The row is kept for reference.
+    pub fn lift(&self, conn: &MysqlConnection) -> Result<Self> {
+        diesel::update(bans::table.find(self.id))
+            .set(bans::expires_at.eq(now.nullable()))
+            .execute(conn)?;
+        Self::find_by_id(conn, self.id)
+    }
+
+    pub fn get_public(&self) -> BanPublic {
+        BanPublic {
+            id: self.id,
+            cidr: match (&self.ip_start, self.prefix_length) {
+                (Some(ip_start), Some(prefix_length)) => {
+                    Some(format!("{}/{}", ip_from_bytes(ip_start), prefix_length))
+                }
+                _ => None,
+            },
+            user_id: self.user_id,
+            reason: self.reason.clone(),
+            expires_at: self.expires_at.map(|x| DateTime::<Utc>::from_utc(x, Utc)),
+            author_id: self.author_id,
+            author_name: self.author_name.clone(),
+            created_at: DateTime::<Utc>::from_utc(self.created_at, Utc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+
+    #[test]
+    fn test_ip_range() {
+        let range = IpRange::from_str("192.0.2.130/25").expect("must succeed");
+        assert_eq!(vec![192, 0, 2, 128], range.start);
+        assert_eq!(vec![192, 0, 2, 255], range.end);
+        assert_eq!(25, range.prefix_length);
+
+        let range = IpRange::from_str("127.0.0.3").expect("must succeed");
+        assert_eq!(vec![127, 0, 0, 3], range.start);
+        assert_eq!(vec![127, 0, 0, 3], range.end);
+        assert_eq!(32, range.prefix_length);
+
+        let range = IpRange::from_str("2001:db8::1/32").expect("must succeed");
+        assert_eq!(16, range.start.len());
+        assert_eq!(
+            vec![0x20, 0x01, 0x0d, 0xb8, 0, 0],
+            range.start[0..6].to_vec()
+        );
+        assert_eq!(
+            vec![0x20, 0x01, 0x0d, 0xb8, 0xff, 0xff],
+            range.end[0..6].to_vec()
+        );
+
+        assert!(IpRange::from_str("192.0.2.0/33").is_err());
+        assert!(IpRange::from_str("not an ip").is_err());
+    }
+
+    #[test]
+    fn test_ban() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let range = IpRange::from_str("192.0.2.0/24").expect("must succeed");
+            let ban = Ban::create(
+                &conn,
+                Some(&range),
+                None,
+                "spam",
+                None,
+                Some(3),
+                Some("admin"),
+            )
+            .expect("must succeed");
+            assert_eq!(Some("192.0.2.0/24".to_owned()), ban.get_public().cidr);
+
+            let inside = IpAddr::from_str("192.0.2.77").expect("must succeed");
+            let outside = IpAddr::from_str("192.0.3.1").expect("must succeed");
+            let found = Ban::find_active(&conn, None, &inside).expect("must succeed");
+            assert_eq!(Some(ban.id), found.map(|x| x.id));
+            let found = Ban::find_active(&conn, None, &outside).expect("must succeed");
+            assert!(found.is_none());
+
+            ban.lift(&conn).expect("must succeed");
+            let found = Ban::find_active(&conn, None, &inside).expect("must succeed");
+            assert!(found.is_none());
+
+            let user_ban = Ban::create(
+                &conn,
+                None,
+                Some(12345),
+                "spam",
+                None,
+                Some(3),
+                Some("admin"),
+            )
+            .expect("must succeed");
+            let found = Ban::find_active(&conn, Some(12345), &outside).expect("must succeed");
+            assert_eq!(Some(user_ban.id), found.map(|x| x.id));
+            Ok(())
+        });
+    }
+}
@@ -1,5 +1,5 @@
-use crate::models::Topic;
-use crate::schema::{boards, topics};
+use crate::models::{ip_from_bytes, pseudonym, Topic};
+use crate::schema::{boards, comments, topics};
 use anyhow::Result;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use diesel::prelude::*;
@@ -10,34 +10,148 @@ pub struct Board {
     pub display_name: String,
     pub name: String,
     pub is_active: bool,
+    pub allow_anonymous_reactions: bool,
+    /// Authors of topics and comments are shown as per-topic pseudonyms.
+    pub is_anonymous: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+/// How topics of a board are listed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TopicOrder {
+    /// Pinned topics first, then by the latest activity.
+    Bumped,
+    /// Newest first.
+    Created,
+}
+
+#[derive(Identifiable, AsChangeset, Debug)]
+#[table_name = "boards"]
+pub struct BoardForm {
+    pub id: i32,
+    pub display_name: Option<String>,
+    pub name: Option<String>,
+    pub is_active: Option<bool>,
+    pub allow_anonymous_reactions: Option<bool>,
+    pub is_anonymous: Option<bool>,
+}
+
+impl BoardForm {
+    pub fn save(&self, conn: &MysqlConnection) -> Result<Board> {
+        let board = self.save_changes::<Board>(conn)?;
+        Ok(board)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "boards"]
+struct NewBoard<'a> {
+    pub display_name: &'a str,
+    pub name: &'a str,
+}
+
 impl Board {
+    pub fn create(conn: &MysqlConnection, display_name: &str, name: &str) -> Result<Self> {
+        let new_board = NewBoard { display_name, name };
+        diesel::insert_into(boards::table)
+            .values(new_board)
+            .execute(conn)?;
+        // `name` is unique, so it identifies the inserted row.
+        Self::find_by_name(conn, name)
+    }
     pub fn get_all(conn: &MysqlConnection) -> Result<Vec<Self>> {
         let results = boards::table.load::<Self>(conn)?;
         Ok(results)
     }
+    /// Fills in the pseudonyms on every anonymous board, for posts from before it was anonymous.
+    pub fn fill_all_pseudonyms(conn: &MysqlConnection) -> Result<usize> {
+        let boards = boards::table
+            .filter(boards::is_anonymous.eq(true))
+            .load::<Self>(conn)?;
+        let mut count = 0;
+        for board in boards {
+            count += board.fill_pseudonyms(conn)?;
+        }
+        Ok(count)
+    }
+    /// Gives pseudonyms to the topics and comments on the board that have none,
+    /// the same ones their authors get in new posts. `updated_at` is kept.
+    pub fn fill_pseudonyms(&self, conn: &MysqlConnection) -> Result<usize> {
+        let topics = topics::table
+            .filter(topics::board_id.eq(self.id))
+            .filter(topics::anonymous_name.is_null())
+            .select((topics::id, topics::author_id, topics::author_ip))
+            .load::<(i32, Option<i32>, Vec<u8>)>(conn)?;
+        for (id, author_id, author_ip) in &topics {
+            let name = pseudonym(*id, *author_id, &ip_from_bytes(author_ip));
+            diesel::update(topics::table.find(id))
+                .set((
+                    topics::anonymous_name.eq(name),
+                    topics::updated_at.eq(topics::updated_at),
+                ))
+                .execute(conn)?;
+        }
+        let comments = comments::table
+            .inner_join(topics::table)
+            .filter(topics::board_id.eq(self.id))
+            .filter(comments::anonymous_name.is_null())
+            .select((
+                comments::id,
+                comments::topic_id,
+                comments::author_id,
+                comments::author_ip,
+            ))
+            .load::<(i32, i32, Option<i32>, Vec<u8>)>(conn)?;
+        for (id, topic_id, author_id, author_ip) in &comments {
+            let name = pseudonym(*topic_id, *author_id, &ip_from_bytes(author_ip));
+            diesel::update(comments::table.find(id))
+                .set((
+                    comments::anonymous_name.eq(name),
+                    comments::updated_at.eq(comments::updated_at),
+                ))
+                .execute(conn)?;
+        }
+        Ok(topics.len() + comments.len())
+    }
+    pub fn get_active(conn: &MysqlConnection) -> Result<Vec<Self>> {
+        let results = boards::table
+            .filter(boards::is_active.eq(true))
+            .load::<Self>(conn)?;
+        Ok(results)
+    }
     pub fn find_by_id(conn: &MysqlConnection, id: i32) -> Result<Self> {
         let post = boards::table.find(id).first::<Self>(conn)?;
         Ok(post)
     }
+    pub fn find_by_name(conn: &MysqlConnection, name: &str) -> Result<Self> {
+        let board = boards::table
+            .filter(boards::name.eq(name))
+            .first::<Self>(conn)?;
+        Ok(board)
+    }
     pub fn get_topics(
         &self,
         conn: &MysqlConnection,
         limit: i32,
         offset: i32,
         include_hidden: bool,
+        order: TopicOrder,
     ) -> Result<Vec<Topic>> {
         let mut query = topics::table.into_boxed();
         query = query.filter(topics::board_id.eq(self.id));
         if !include_hidden {
             query = query.filter(topics::is_hidden.eq(false));
         }
+        query = match order {
+            TopicOrder::Bumped => query
+                .order_by(topics::is_pinned.desc())
+                .then_order_by(topics::updated_at.desc()),
+            TopicOrder::Created => query
+                .order_by(topics::created_at.desc())
+                .then_order_by(topics::id.desc()),
+        };
         let topics = query
-            .order_by(topics::is_pinned.desc())
-            .then_order_by(topics::updated_at.desc())
             .limit(limit.into())
             .offset(offset.into())
             .load::<Topic>(conn)?;
@@ -49,6 +163,8 @@ impl Board {
             display_name: self.display_name.clone(),
             name: self.name.clone(),
             is_active: self.is_active,
+            allow_anonymous_reactions: self.allow_anonymous_reactions,
+            is_anonymous: self.is_anonymous,
             created_at: DateTime::<Utc>::from_utc(self.created_at, Utc),
             updated_at: DateTime::<Utc>::from_utc(self.updated_at, Utc),
         }
@@ -61,6 +177,126 @@ pub struct BoardPublic {
     pub display_name: String,
     pub name: String,
     pub is_active: bool,
+    pub allow_anonymous_reactions: bool,
+    pub is_anonymous: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+
+    #[test]
+    fn test_board() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = Board::create(&conn, "테스트게시판", "test_board").expect("must succeed");
+            assert_eq!("테스트게시판", board.display_name);
+            assert_eq!("test_board", board.name);
+            assert_eq!(true, board.is_active);
+            assert_eq!(false, board.allow_anonymous_reactions);
+            assert_eq!(false, board.is_anonymous);
+
+            let changed = BoardForm {
+                id: board.id,
+                display_name: Some("바뀐게시판".to_owned()),
+                name: None,
+                is_active: Some(false),
+                allow_anonymous_reactions: Some(true),
+                is_anonymous: None,
+            }
+            .save(&conn)
+            .expect("must succeed");
+            assert_eq!("바뀐게시판", changed.display_name);
+            assert_eq!("test_board", changed.name);
+            assert_eq!(false, changed.is_active);
+            assert_eq!(true, changed.allow_anonymous_reactions);
+            Ok(())
+        });
+    }
+    #[test]
+    fn test_topic_order() {
+        use crate::models::TopicForm;
+        use std::net::IpAddr;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = Board::create(&conn, "정렬테스트", "test_order").expect("must succeed");
+            let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+            let first = Topic::create(&conn, &board, "first", Some(3), Some("test"), &ip)
+                .expect("must succeed");
+            let second = Topic::create(&conn, &board, "second", Some(3), Some("test"), &ip)
+                .expect("must succeed");
+            TopicForm {
+                id: first.id,
+                board_id: None,
+                title: None,
+                is_closed: None,
+                is_suspended: None,
+                is_hidden: None,
+                is_pinned: Some(true),
+            }
+            .save(&conn)
+            .expect("must succeed");
+
+            let topics = board
+                .get_topics(&conn, 10, 0, false, TopicOrder::Bumped)
+                .expect("must succeed");
+            assert_eq!(
+                vec![first.id, second.id],
+                topics.iter().map(|x| x.id).collect::<Vec<i32>>()
+            );
+            let topics = board
+                .get_topics(&conn, 10, 0, false, TopicOrder::Created)
+                .expect("must succeed");
+            assert_eq!(
+                vec![second.id, first.id],
+                topics.iter().map(|x| x.id).collect::<Vec<i32>>()
+            );
+            Ok(())
+        });
+    }
+    #[test]
+    fn test_fill_pseudonyms() {
+        use crate::models::Comment;
+        use std::net::IpAddr;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = Board::create(&conn, "익명전환", "test_fill").expect("must succeed");
+            let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+            let topic = Topic::create(&conn, &board, "title", Some(3), Some("test"), &ip)
+                .expect("must succeed");
+            let comment =
+                Comment::create(&conn, &topic, None, "a", None, None, &ip).expect("must succeed");
+            assert_eq!(None, topic.anonymous_name);
+
+            let board = BoardForm {
+                id: board.id,
+                display_name: None,
+                name: None,
+                is_active: None,
+                allow_anonymous_reactions: None,
+                is_anonymous: Some(true),
+            }
+            .save(&conn)
+            .expect("must succeed");
+            assert_eq!(2, board.fill_pseudonyms(&conn).expect("must succeed"));
+            assert_eq!(0, board.fill_pseudonyms(&conn).expect("must succeed"));
+
+            // Later posts by the same authors get the same names
+            let topic = Topic::find_by_id(&conn, topic.id).expect("must succeed");
+            let later = Comment::create(&conn, &topic, None, "b", Some(3), Some("test"), &ip)
+                .expect("must succeed");
+            assert_eq!(topic.anonymous_name, later.anonymous_name);
+            let comment = Comment::find_by_id(&conn, comment.id).expect("must succeed");
+            let later =
+                Comment::create(&conn, &topic, None, "c", None, None, &ip).expect("must succeed");
+            assert_eq!(true, comment.anonymous_name.is_some());
+            assert_eq!(comment.anonymous_name, later.anonymous_name);
+            Ok(())
+        });
+    }
+}
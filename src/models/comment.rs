@@ -1,20 +1,31 @@
-use crate::models::Topic;
-use crate::schema::comments;
+use crate::db::last_insert_id;
+use crate::markdown::RENDER_CACHE;
+use crate::models::{
+    display_ip, pseudonym, CommentMention, CommentReaction, CommentRevision, MentionSpan,
+    Notification, NotificationKind, ReactionCount, SearchFilter, Topic, TopicWatch,
+};
+use crate::schema::{comment_revisions, comments, topics};
 use anyhow::Result;
 use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::dsl::sql;
 use diesel::prelude::*;
-use std::convert::TryInto;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use diesel::sql_types::{BigInt, Bool, Text};
+use std::collections::HashMap;
+use std::net::IpAddr;
 
 #[derive(Serialize, Deserialize, Queryable, Identifiable, AsChangeset, Debug)]
 pub struct Comment {
     pub id: i32,
     pub topic_id: i32,
+    pub parent_id: Option<i32>,
     pub content: String,
     pub author_id: Option<i32>,
     pub author_name: Option<String>,
     pub author_ip: Vec<u8>,
+    /// Set on anonymous boards, where it is shown instead of the author.
+    pub anonymous_name: Option<String>,
     pub is_hidden: bool,
+    pub edited_at: Option<NaiveDateTime>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -37,20 +48,31 @@ impl CommentForm {
 #[table_name = "comments"]
 struct NewComment<'a> {
     pub topic_id: i32,
+    pub parent_id: Option<i32>,
     pub content: &'a str,
     pub author_id: Option<i32>,
     pub author_name: Option<&'a str>,
     pub author_ip: Vec<u8>,
+    pub anonymous_name: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Hash, Debug)]
 pub struct CommentPublic {
     pub id: i32,
     pub topic_id: i32,
+    pub parent_id: Option<i32>,
+    pub reply_count: i64,
+    pub reactions: Vec<ReactionCount>,
     pub content: Option<String>,
+    /// `content` rendered from Markdown to sanitized HTML.
+    pub content_html: Option<String>,
+    /// Empty if `content` is not shown.
+    pub mentions: Vec<MentionSpan>,
     pub author_id: Option<i32>,
     pub author_name: String,
+    pub anonymous_name: Option<String>,
     pub is_hidden: bool,
+    pub is_edited: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -59,26 +81,44 @@ impl Comment {
     pub fn create(
         conn: &MysqlConnection,
         topic: &Topic,
+        parent: Option<&Comment>,
         content: &str,
         author_id: Option<i32>,
         author_name: Option<&str>,
         author_ip: &IpAddr,
-    ) -> Result<()> {
+    ) -> Result<Self> {
         let ip_bin: Vec<u8> = match &author_ip {
             IpAddr::V4(ip) => ip.octets().to_vec(),
             IpAddr::V6(ip) => ip.octets().to_vec(),
         };
+        let anonymous_name = if topic.get_board(conn)?.is_anonymous {
+            Some(pseudonym(topic.id, author_id, author_ip))
+        } else {
+            None
+        };
         let new_comment = NewComment {
             topic_id: topic.id,
+            parent_id: parent.map(|x| x.id),
             content,
             author_id,
             author_name,
             author_ip: ip_bin,
+            anonymous_name,
         };
         diesel::insert_into(comments::table)
             .values(new_comment)
             .execute(conn)?;
-        Ok(())
+        let comment = Self::find_by_id(conn, last_insert_id(conn)?)?;
+        // Authors watch the topics they comment on, including the ones they create
+        if let Some(author_id) = author_id {
+            TopicWatch::add(conn, topic.id, author_id)?;
+        }
+        let mentioned = CommentMention::refresh(conn, &comment)?;
+        if !comment.is_hidden {
+            Notification::create_many(conn, NotificationKind::Mention, &comment, &mentioned)?;
+            Notification::notify_watchers(conn, &comment, &mentioned)?;
+        }
+        Ok(comment)
     }
 
     pub fn get_all(conn: &MysqlConnection, limit: i32, offset: i32) -> Result<Vec<Self>> {
@@ -95,43 +135,190 @@ impl Comment {
         Ok(comment)
     }
 
+    /// Searches visible comments on visible topics by content.
+    /// `query` is a full-text boolean mode query.
+    pub fn search(
+        conn: &MysqlConnection,
+        query: &str,
+        filter: &SearchFilter,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<(Self, Topic)>> {
+        let mut search = comments::table
+            .inner_join(topics::table)
+            .filter(
+                sql::<Bool>("MATCH (comments.content) AGAINST (")
+                    .bind::<Text, _>(query.to_owned())
+                    .sql(" IN BOOLEAN MODE)"),
+            )
+            .filter(comments::is_hidden.eq(false))
+            .filter(topics::is_hidden.eq(false))
+            .into_boxed();
+        if let Some(board_id) = filter.board_id {
+            search = search.filter(topics::board_id.eq(board_id));
+        }
+        if let Some(author_id) = filter.author_id {
+            // Anonymous comments must not be found by their authors
+            search = search
+                .filter(comments::author_id.eq(author_id))
+                .filter(comments::anonymous_name.is_null());
+        }
+        if let Some(from) = filter.from {
+            search = search.filter(comments::created_at.ge(from));
+        }
+        if let Some(to) = filter.to {
+            search = search.filter(comments::created_at.lt(to));
+        }
+        let results = search
+            .order_by(comments::created_at.desc())
+            .limit(limit.into())
+            .offset(offset.into())
+            .load::<(Self, Topic)>(conn)?;
+        Ok(results)
+    }
+
+    pub fn get_replies(
+        &self,
+        conn: &MysqlConnection,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<Self>> {
+        let replies = comments::table
+            .filter(comments::parent_id.eq(self.id))
+            .order_by(comments::id.asc())
+            .limit(limit.into())
+            .offset(offset.into())
+            .load::<Self>(conn)?;
+        Ok(replies)
+    }
+
+    /// Counts the replies to each of `ids`. Comments without replies are left out.
+    pub fn get_reply_counts(conn: &MysqlConnection, ids: &[i32]) -> Result<HashMap<i32, i64>> {
+        let counts = comments::table
+            .filter(comments::parent_id.eq_any(ids))
+            .group_by(comments::parent_id)
+            .select((comments::parent_id, sql::<BigInt>("COUNT(*)")))
+            .load::<(Option<i32>, i64)>(conn)?;
+        Ok(counts
+            .into_iter()
+            .filter_map(|(id, count)| id.map(|id| (id, count)))
+            .collect())
+    }
+
+    /// Converts comments for responses, filling in what has to be queried.
+    pub fn get_public_list(
+        conn: &MysqlConnection,
+        comments: &[Self],
+        show_hidden: bool,
+    ) -> Result<Vec<CommentPublic>> {
+        let ids = comments.iter().map(|x| x.id).collect::<Vec<i32>>();
+        let reply_counts = Self::get_reply_counts(conn, &ids)?;
+        let mut reactions = CommentReaction::get_counts(conn, &ids)?;
+        let mut mentions = CommentMention::get_spans(conn, &ids)?;
+        Ok(comments
+            .iter()
+            .map(|x| {
+                let public = x.get_public(show_hidden);
+                CommentPublic {
+                    reply_count: reply_counts.get(&x.id).copied().unwrap_or(0),
+                    reactions: reactions.remove(&x.id).unwrap_or_default(),
+                    mentions: match public.content {
+                        Some(_) => mentions.remove(&x.id).unwrap_or_default(),
+                        None => Vec::new(),
+                    },
+                    ..public
+                }
+            })
+            .collect())
+    }
+
+    pub fn get_topic(&self, conn: &MysqlConnection) -> Result<Topic> {
+        let topic = topics::table.find(self.topic_id).first::<Topic>(conn)?;
+        Ok(topic)
+    }
+
+    /// Replaces the content, keeping the previous one as a revision.
+    pub fn edit(
+        &self,
+        conn: &MysqlConnection,
+        content: &str,
+        user_id: Option<i32>,
+        user_name: Option<&str>,
+        user_ip: &IpAddr,
+    ) -> Result<Self> {
+        CommentRevision::create(conn, self.id, &self.content, user_id, user_name, user_ip)?;
+        diesel::update(comments::table.find(self.id))
+            .set((
+                comments::content.eq(content),
+                comments::edited_at.eq(diesel::dsl::now.nullable()),
+            ))
+            .execute(conn)?;
+        let edited = Self::find_by_id(conn, self.id)?;
+        // Only users who were not mentioned before are notified
+        let previous = CommentMention::get_spans(conn, &[self.id])?
+            .remove(&self.id)
+            .unwrap_or_default()
+            .iter()
+            .map(|x| x.user_id)
+            .collect::<Vec<i32>>();
+        let mentioned = CommentMention::refresh(conn, &edited)?
+            .into_iter()
+            .filter(|x| !previous.contains(x))
+            .collect::<Vec<i32>>();
+        if !edited.is_hidden {
+            Notification::create_many(conn, NotificationKind::Mention, &edited, &mentioned)?;
+        }
+        Ok(edited)
+    }
+
+    pub fn get_revisions(&self, conn: &MysqlConnection) -> Result<Vec<CommentRevision>> {
+        let revisions = comment_revisions::table
+            .filter(comment_revisions::comment_id.eq(self.id))
+            .order_by(comment_revisions::id.asc())
+            .load::<CommentRevision>(conn)?;
+        Ok(revisions)
+    }
+
     pub fn has_ipv6(&self) -> bool {
         self.author_ip[4..].iter().any(|x| *x != 0u8)
     }
 
+    /// `reply_count`, `reactions` and `mentions` are left empty.
+    /// Use `get_public_list` to fill them in.
+    /// The author of an anonymous comment is only shown with `show_hidden`.
     pub fn get_public(&self, show_hidden: bool) -> CommentPublic {
+        let is_shown = show_hidden || !self.is_hidden;
+        let is_masked = self.anonymous_name.is_some() && !show_hidden;
         CommentPublic {
             id: self.id,
             topic_id: self.topic_id,
-            content: if show_hidden || !self.is_hidden {
+            parent_id: self.parent_id,
+            reply_count: 0,
+            reactions: Vec::new(),
+            content: if is_shown {
                 Some(self.content.clone())
             } else {
                 None
             },
-            author_id: self.author_id,
-            author_name: if let Some(name) = &self.author_name {
-                name.clone()
+            content_html: if is_shown {
+                Some(RENDER_CACHE.render(&self.content).to_string())
             } else {
-                self.get_ip_string()
+                None
+            },
+            mentions: Vec::new(),
+            author_id: if is_masked { None } else { self.author_id },
+            author_name: match (&self.anonymous_name, &self.author_name) {
+                (Some(anonymous_name), _) if is_masked => anonymous_name.clone(),
+                (_, Some(name)) => name.clone(),
+                (_, None) => display_ip(&self.author_ip),
             },
+            anonymous_name: self.anonymous_name.clone(),
             is_hidden: self.is_hidden,
+            is_edited: self.edited_at.is_some(),
             created_at: DateTime::<Utc>::from_utc(self.created_at, Utc),
             updated_at: DateTime::<Utc>::from_utc(self.updated_at, Utc),
         }
     }
-
-    fn get_ip_string(&self) -> String {
-        let x: &Vec<u8> = &self.author_ip;
-        if x[4..].iter().any(|x| *x != 0u8) {
-            // IPv6
-            let arr: &[u8; 16] = x[..].try_into().unwrap();
-            Ipv6Addr::from(*arr).to_string()
-        } else {
-            // IPv4
-            let arr: &[u8; 4] = x[0..4].try_into().unwrap();
-            Ipv4Addr::from(*arr).to_string()
-        }
-    }
 }
 
 #[cfg(test)]
@@ -139,6 +326,7 @@ mod tests {
     use super::*;
     use crate::db::create_connection;
     use crate::models::Board;
+    use std::convert::TryInto;
 
     #[test]
     fn test_topic() {
@@ -147,7 +335,7 @@ mod tests {
         conn.test_transaction::<_, diesel::result::Error, _>(|| {
             let boards = Board::get_all(&conn).expect("A board must exist");
             let ip = IpAddr::from_str("2001:db8::1").expect("must succeed");
-            Topic::create(
+            let topic = Topic::create(
                 &conn,
                 &boards[0],
                 "test title",
@@ -156,22 +344,56 @@ mod tests {
                 &ip,
             )
             .expect("must succeed");
-            let topics = Topic::get_all(&conn, 1, 0).expect("must succeed");
-            Comment::create(
+            let created = Comment::create(
                 &conn,
-                &topics[0],
+                &topic,
+                None,
                 "Test content",
                 Some(3),
                 Some("test_author"),
                 &ip,
             )
             .expect("must succeed");
+            assert_eq!(topic.id, created.topic_id);
             let comments = Comment::get_all(&conn, 1, 0).expect("must succeed");
+            assert_eq!(created.id, comments[0].id);
             assert_eq!("Test content", comments[0].content);
             assert_eq!(Some("test_author".to_owned()), comments[0].author_name);
             assert_eq!(true, comments[0].has_ipv6());
             let arr: &[u8; 16] = comments[0].author_ip[..].try_into().expect("must succeed");
             assert_eq!(ip, IpAddr::from(*arr));
+            assert_eq!(false, comments[0].get_public(false).is_edited);
+
+            let edited = comments[0]
+                .edit(&conn, "Edited content", Some(3), Some("test_author"), &ip)
+                .expect("must succeed");
+            assert_eq!("Edited content", edited.content);
+            assert_eq!(true, edited.get_public(false).is_edited);
+            let revisions = edited.get_revisions(&conn).expect("must succeed");
+            assert_eq!(1, revisions.len());
+            assert_eq!("Test content", revisions[0].content);
+
+            let hidden = CommentForm {
+                id: edited.id,
+                is_hidden: Some(true),
+            }
+            .save(&conn)
+            .expect("must succeed");
+            assert_eq!("Edited content", hidden.content);
+            assert_eq!(None, hidden.get_public(false).content);
+
+            let reply = Comment::create(&conn, &topic, Some(&hidden), "Reply", None, None, &ip)
+                .expect("must succeed");
+            assert_eq!(Some(hidden.id), reply.get_public(false).parent_id);
+            let replies = hidden.get_replies(&conn, 10, 0).expect("must succeed");
+            assert_eq!(
+                vec![reply.id],
+                replies.iter().map(|x| x.id).collect::<Vec<i32>>()
+            );
+            let public =
+                Comment::get_public_list(&conn, &[hidden, reply], false).expect("must succeed");
+            assert_eq!(1, public[0].reply_count);
+            assert_eq!(0, public[1].reply_count);
 
             Ok(())
         });
@@ -0,0 +1,80 @@
+use crate::schema::comment_revisions;
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use std::net::IpAddr;
+
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+pub struct CommentRevision {
+    pub id: i32,
+    pub comment_id: i32,
+    pub content: String,
+    pub user_id: Option<i32>,
+    pub user_name: Option<String>,
+    pub user_ip: Vec<u8>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "comment_revisions"]
+struct NewCommentRevision<'a> {
+    pub comment_id: i32,
+    pub content: &'a str,
+    pub user_id: Option<i32>,
+    pub user_name: Option<&'a str>,
+    pub user_ip: Vec<u8>,
+}
+
+/// A revision holds the content a comment had before an edit,
+/// along with the user who made that edit.
+#[derive(Serialize, Deserialize, Hash, Debug)]
+pub struct CommentRevisionPublic {
+    pub id: i32,
+    pub comment_id: i32,
+    pub content: Option<String>,
+    pub user_id: Option<i32>,
+    pub user_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CommentRevision {
+    pub fn create(
+        conn: &MysqlConnection,
+        comment_id: i32,
+        content: &str,
+        user_id: Option<i32>,
+        user_name: Option<&str>,
+        user_ip: &IpAddr,
+    ) -> Result<()> {
+        let ip_bin: Vec<u8> = match &user_ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        let new_revision = NewCommentRevision {
+            comment_id,
+            content,
+            user_id,
+            user_name,
+            user_ip: ip_bin,
+        };
+        diesel::insert_into(comment_revisions::table)
+            .values(new_revision)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn get_public(&self, show_hidden: bool) -> CommentRevisionPublic {
+        CommentRevisionPublic {
+            id: self.id,
+            comment_id: self.comment_id,
+            content: if show_hidden {
+                Some(self.content.clone())
+            } else {
+                None
+            },
+            user_id: self.user_id,
+            user_name: self.user_name.clone(),
+            created_at: DateTime::<Utc>::from_utc(self.created_at, Utc),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::models::{escape_html, BoardPublic, CommentPublic, TopicPublic};
+use chrono::{DateTime, SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use std::cmp::Reverse;
+use std::env;
+
+lazy_static! {
+    /// Base URL of the BBS front end, which entries link to.
+    static ref BBS_URL: String = env::var("BBS_URL")
+        .unwrap_or_else(|_| "https://bbs.librewiki.net".to_owned())
+        .trim_end_matches('/')
+        .to_owned();
+}
+
+/// Number of entries in a feed.
+pub const FEED_SIZE: i32 = 20;
+
+#[derive(Serialize, Hash, Debug)]
+pub struct Feed {
+    pub id: String,
+    pub title: String,
+    pub updated: DateTime<Utc>,
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Serialize, Hash, Debug)]
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub author: String,
+    pub content: Option<String>,
+    pub published: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        escape_html(c, &mut out);
+    }
+    out
+}
+
+fn format_date(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+impl Feed {
+    /// Builds a feed of new topics. Entry ids are also the links to the topics.
+    pub fn from_board(board: &BoardPublic, topics: &[TopicPublic]) -> Self {
+        let mut entries = topics
+            .iter()
+            .map(|x| FeedEntry {
+                id: format!("{}/topics/{}", *BBS_URL, x.id),
+                title: x.title.clone(),
+                author: x.author_name.clone(),
+                content: None,
+                published: x.created_at,
+                updated: x.updated_at,
+            })
+            .collect::<Vec<FeedEntry>>();
+        entries.sort_by_key(|x| Reverse(x.published));
+        Feed {
+            id: format!("{}/boards/{}", *BBS_URL, board.id),
+            title: board.display_name.clone(),
+            updated: entries
+                .iter()
+                .map(|x| x.updated)
+                .max()
+                .unwrap_or(board.updated_at),
+            entries,
+        }
+    }
+
+    /// Builds a feed of new comments, newest first.
+    pub fn from_topic(topic: &TopicPublic, comments: &[CommentPublic]) -> Self {
+        let entries = comments
+            .iter()
+            .rev()
+            .map(|x| FeedEntry {
+                id: format!("{}/topics/{}#comment-{}", *BBS_URL, topic.id, x.id),
+                title: format!("{} #{}", topic.title, x.id),
+                author: x.author_name.clone(),
+                content: x.content.clone(),
+                published: x.created_at,
+                updated: x.updated_at,
+            })
+            .collect::<Vec<FeedEntry>>();
+        Feed {
+            id: format!("{}/topics/{}", *BBS_URL, topic.id),
+            title: topic.title.clone(),
+            updated: entries
+                .iter()
+                .map(|x| x.updated)
+                .max()
+                .unwrap_or(topic.updated_at),
+            entries,
+        }
+    }
+
+    pub fn to_atom(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+        out.push_str(&format!("  <id>{}</id>\n", escape(&self.id)));
+        out.push_str(&format!("  <title>{}</title>\n", escape(&self.title)));
+        out.push_str(&format!(
+            "  <link rel=\"alternate\" href=\"{}\"/>\n",
+            escape(&self.id)
+        ));
+        out.push_str(&format!(
+            "  <updated>{}</updated>\n",
+            format_date(&self.updated)
+        ));
+        for entry in &self.entries {
+            out.push_str("  <entry>\n");
+            out.push_str(&format!("    <id>{}</id>\n", escape(&entry.id)));
+            out.push_str(&format!("    <title>{}</title>\n", escape(&entry.title)));
+            out.push_str(&format!(
+                "    <link rel=\"alternate\" href=\"{}\"/>\n",
+                escape(&entry.id)
+            ));
+            out.push_str(&format!(
+                "    <author><name>{}</name></author>\n",
+                escape(&entry.author)
+            ));
+            out.push_str(&format!(
+                "    <published>{}</published>\n",
+                format_date(&entry.published)
+            ));
+            out.push_str(&format!(
+                "    <updated>{}</updated>\n",
+                format_date(&entry.updated)
+            ));
+            if let Some(content) = &entry.content {
+                out.push_str(&format!(
+                    "    <content type=\"text\">{}</content>\n",
+                    escape(content)
+                ));
+            }
+            out.push_str("  </entry>\n");
+        }
+        out.push_str("</feed>\n");
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_to_atom() {
+        let date = Utc.ymd(2021, 9, 30).and_hms(12, 0, 0);
+        let feed = Feed {
+            id: "https://bbs.example/topics/1".to_owned(),
+            title: "<리브레> & 위키".to_owned(),
+            updated: date,
+            entries: vec![FeedEntry {
+                id: "https://bbs.example/topics/1#comment-2".to_owned(),
+                title: "<리브레> & 위키 #2".to_owned(),
+                author: "test_author".to_owned(),
+                content: Some("a < b".to_owned()),
+                published: date,
+                updated: date,
+            }],
+        };
+        let atom = feed.to_atom();
+        assert_eq!(
+            true,
+            atom.contains("<title>&lt;리브레&gt; &amp; 위키</title>")
+        );
+        assert_eq!(
+            true,
+            atom.contains("<updated>2021-09-30T12:00:00Z</updated>")
+        );
+        assert_eq!(
+            true,
+            atom.contains("<content type=\"text\">a &lt; b</content>")
+        );
+        assert_eq!(1, atom.matches("<entry>").count());
+    }
+}
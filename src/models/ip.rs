@@ -0,0 +1,127 @@
+use lazy_static::lazy_static;
+use ring::hmac;
+use std::convert::TryInto;
+use std::env;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+lazy_static! {
+    pub static ref IP_DISPLAY: IpDisplay = match env::var("IP_DISPLAY") {
+        Ok(x) => IpDisplay::from_str(&x).expect("IP_DISPLAY is invalid"),
+        Err(_) => IpDisplay::Masked,
+    };
+    pub static ref IP_HASH_KEY: hmac::Key = hmac::Key::new(
+        hmac::HMAC_SHA256,
+        env::var("IP_HASH_SECRET")
+            .expect("IP_HASH_SECRET is not set")
+            .as_bytes(),
+    );
+}
+
+/// How the IPs of authors who are not logged in are shown in their place.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum IpDisplay {
+    /// `192.0.2.1`
+    Full,
+    /// `192.0.x.x`, or the /64 prefix of an IPv6 address
+    Masked,
+    /// A keyed hash, which tells authors apart without revealing their IPs
+    Hashed,
+}
+
+impl FromStr for IpDisplay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "full" => Ok(IpDisplay::Full),
+            "masked" => Ok(IpDisplay::Masked),
+            "hashed" => Ok(IpDisplay::Hashed),
+            _ => Err(anyhow::anyhow!("Unknown IP display: {}", s)),
+        }
+    }
+}
+
+impl IpDisplay {
+    pub fn format(&self, ip: &IpAddr) -> String {
+        match self {
+            IpDisplay::Full => ip.to_string(),
+            IpDisplay::Masked => mask_ip(ip),
+            IpDisplay::Hashed => hash_ip(&IP_HASH_KEY, ip),
+        }
+    }
+}
+
+/// Reads an IP in the binary form it is stored in: 4 bytes for IPv4 and 16 bytes for IPv6.
+pub fn ip_from_bytes(x: &[u8]) -> IpAddr {
+    if x.len() == 16 {
+        let arr: [u8; 16] = x.try_into().unwrap();
+        Ipv6Addr::from(arr).into()
+    } else {
+        let arr: [u8; 4] = x[0..4].try_into().unwrap();
+        Ipv4Addr::from(arr).into()
+    }
+}
+
+/// Formats a stored IP according to `IP_DISPLAY`.
+pub fn display_ip(x: &[u8]) -> String {
+    IP_DISPLAY.format(&ip_from_bytes(x))
+}
+
+fn mask_ip(ip: &IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            format!("{}.{}.x.x", octets[0], octets[1])
+        }
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            let prefix = Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0);
+            format!("{}/64", prefix)
+        }
+    }
+}
+
+fn hash_ip(key: &hmac::Key, ip: &IpAddr) -> String {
+    let tag = match ip {
+        IpAddr::V4(ip) => hmac::sign(key, &ip.octets()),
+        IpAddr::V6(ip) => hmac::sign(key, &ip.octets()),
+    };
+    let hex = tag.as_ref()[..4]
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>();
+    format!("#{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let v4 = IpAddr::from_str("123.45.67.89").expect("must succeed");
+        let v6 = IpAddr::from_str("2001:db8:1:2:3:4:5:6").expect("must succeed");
+        assert_eq!(v4, ip_from_bytes(&[123, 45, 67, 89]));
+        // Stored by length, so a v6 address with zeros after the first 4 bytes stays v6
+        let zeros = IpAddr::from_str("2001:db8::").expect("must succeed");
+        match zeros {
+            IpAddr::V6(ip) => assert_eq!(zeros, ip_from_bytes(&ip.octets())),
+            IpAddr::V4(_) => unreachable!(),
+        }
+
+        assert_eq!("123.45.67.89", IpDisplay::Full.format(&v4));
+        assert_eq!("123.45.x.x", IpDisplay::Masked.format(&v4));
+        assert_eq!("2001:db8:1:2::/64", IpDisplay::Masked.format(&v6));
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"test secret");
+        let hashed = hash_ip(&key, &v4);
+        assert_eq!(9, hashed.len());
+        assert_eq!(hashed, hash_ip(&key, &v4));
+        assert_ne!(hashed, hash_ip(&key, &v6));
+        assert_eq!(
+            Ok(IpDisplay::Hashed),
+            IpDisplay::from_str("hashed").map_err(|_| ())
+        );
+    }
+}
@@ -1,11 +1,17 @@
 use std::net::IpAddr;
 
-use crate::schema::logs;
+use crate::models::ip_from_bytes;
+use crate::schema::{log_types, logs};
 use anyhow::Result;
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
+use diesel::dsl::sql;
+use diesel::mysql::Mysql;
 use diesel::prelude::*;
+use diesel::sql_types::{Bool, Integer};
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, FromSqlRow)]
 pub enum LogType {
     CloseTopic = 1,
     UncloseTopic = 2,
@@ -17,11 +23,63 @@ pub enum LogType {
     UnhideComment = 8,
     PinTopic = 9,
     UnpinTopic = 10,
+    CreateBoard = 11,
+    RenameBoard = 12,
+    DeactivateBoard = 13,
+    ReactivateBoard = 14,
+    RenameTopic = 15,
+    MoveTopic = 16,
+    Ban = 17,
+    Unban = 18,
+    ConfigureBoard = 19,
+    PurgeIps = 20,
+    ResolveReport = 21,
+    DismissReport = 22,
+    BulkHide = 23,
+    BulkUnhide = 24,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl FromSql<Integer, Mysql> for LogType {
+    fn from_sql(bytes: Option<&<Mysql as Backend>::RawValue>) -> deserialize::Result<Self> {
+        match <i32 as FromSql<Integer, Mysql>>::from_sql(bytes)? {
+            1 => Ok(LogType::CloseTopic),
+            2 => Ok(LogType::UncloseTopic),
+            3 => Ok(LogType::HideTopic),
+            4 => Ok(LogType::UnhideTopic),
+            5 => Ok(LogType::SuspendTopic),
+            6 => Ok(LogType::UnsuspendTopic),
+            7 => Ok(LogType::HideComment),
+            8 => Ok(LogType::UnhideComment),
+            9 => Ok(LogType::PinTopic),
+            10 => Ok(LogType::UnpinTopic),
+            11 => Ok(LogType::CreateBoard),
+            12 => Ok(LogType::RenameBoard),
+            13 => Ok(LogType::DeactivateBoard),
+            14 => Ok(LogType::ReactivateBoard),
+            15 => Ok(LogType::RenameTopic),
+            16 => Ok(LogType::MoveTopic),
+            17 => Ok(LogType::Ban),
+            18 => Ok(LogType::Unban),
+            19 => Ok(LogType::ConfigureBoard),
+            20 => Ok(LogType::PurgeIps),
+            21 => Ok(LogType::ResolveReport),
+            22 => Ok(LogType::DismissReport),
+            23 => Ok(LogType::BulkHide),
+            24 => Ok(LogType::BulkUnhide),
+            n => Err(format!("Unknown log type: {}", n).into()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
 pub struct LogContent {
     pub target: i32,
+    /// Value of the changed field before the action, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<serde_json::Value>,
+    /// Value of the changed field after the action, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<serde_json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
@@ -45,6 +103,29 @@ struct NewLog<'a> {
     pub user_ip: Vec<u8>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LogPublic {
+    pub id: i32,
+    pub log_type: Option<String>,
+    pub content: Option<LogContent>,
+    pub user_id: Option<i32>,
+    pub user_name: Option<String>,
+    pub user_ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Default, Debug)]
+pub struct LogFilter {
+    /// Name of the log type as stored in `log_types`, e.g. `HIDE_COMMENT`.
+    pub log_type: Option<String>,
+    pub target: Option<i32>,
+    pub user_id: Option<i32>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    /// Only logs with a smaller id are returned.
+    pub before: Option<i32>,
+}
+
 impl Log {
     pub fn add(
         conn: &MysqlConnection,
@@ -70,4 +151,108 @@ impl Log {
             .execute(conn)?;
         Ok(())
     }
+
+    /// Returns logs matching `filter`, newest first, with their log type names.
+    pub fn get_all(
+        conn: &MysqlConnection,
+        filter: &LogFilter,
+        limit: i32,
+    ) -> Result<Vec<(Self, Option<String>)>> {
+        let mut query = logs::table
+            .inner_join(log_types::table)
+            .select((logs::all_columns, log_types::name))
+            .into_boxed();
+        if let Some(log_type) = &filter.log_type {
+            query = query.filter(log_types::name.eq(log_type));
+        }
+        if let Some(target) = filter.target {
+            query = query.filter(
+                sql::<Bool>("JSON_EXTRACT(logs.content, '$.target') = ").bind::<Integer, _>(target),
+            );
+        }
+        if let Some(user_id) = filter.user_id {
+            query = query.filter(logs::user_id.eq(user_id));
+        }
+        if let Some(from) = filter.from {
+            query = query.filter(logs::created_at.ge(from));
+        }
+        if let Some(to) = filter.to {
+            query = query.filter(logs::created_at.lt(to));
+        }
+        if let Some(before) = filter.before {
+            query = query.filter(logs::id.lt(before));
+        }
+        let logs = query
+            .order_by(logs::id.desc())
+            .limit(limit.into())
+            .load::<(Self, Option<String>)>(conn)?;
+        Ok(logs)
+    }
+
+    pub fn get_public(&self, log_type: Option<String>, show_ip: bool) -> LogPublic {
+        LogPublic {
+            id: self.id,
+            log_type,
+            content: serde_json::from_str(&self.content).ok(),
+            user_id: self.user_id,
+            user_name: self.user_name.clone(),
+            user_ip: if show_ip {
+                Some(ip_from_bytes(&self.user_ip).to_string())
+            } else {
+                None
+            },
+            created_at: DateTime::<Utc>::from_utc(self.created_at, Utc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+
+    #[test]
+    fn test_log() {
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+            Log::add(
+                &conn,
+                &LogType::HideComment,
+                &LogContent {
+                    target: 12345,
+                    ..Default::default()
+                },
+                Some(3),
+                Some("test_admin"),
+                &ip,
+            )
+            .expect("must succeed");
+            let filter = LogFilter {
+                log_type: Some("HIDE_COMMENT".to_owned()),
+                target: Some(12345),
+                user_id: Some(3),
+                ..Default::default()
+            };
+            let logs = Log::get_all(&conn, &filter, 10).expect("must succeed");
+            assert_eq!(1, logs.len());
+            let (log, log_type) = &logs[0];
+            let public = log.get_public(log_type.clone(), false);
+            assert_eq!(Some("HIDE_COMMENT".to_owned()), public.log_type);
+            assert_eq!(12345, public.content.expect("must succeed").target);
+            assert_eq!(None, public.user_ip);
+            let public = log.get_public(log_type.clone(), true);
+            assert_eq!(Some("127.0.0.3".to_owned()), public.user_ip);
+
+            let filter = LogFilter {
+                before: Some(log.id),
+                target: Some(12345),
+                ..Default::default()
+            };
+            let logs = Log::get_all(&conn, &filter, 10).expect("must succeed");
+            assert_eq!(0, logs.len());
+            Ok(())
+        });
+    }
 }
@@ -0,0 +1,174 @@
+use crate::models::Comment;
+use crate::schema::{comment_mentions, comments};
+use anyhow::Result;
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+/// MediaWiki does not allow longer user names.
+const MAX_NAME_LENGTH: usize = 85;
+
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+pub struct CommentMention {
+    pub id: i32,
+    pub comment_id: i32,
+    pub user_id: i32,
+    pub user_name: String,
+    pub start_offset: i32,
+    pub end_offset: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "comment_mentions"]
+struct NewCommentMention<'a> {
+    pub comment_id: i32,
+    pub user_id: i32,
+    pub user_name: &'a str,
+    pub start_offset: i32,
+    pub end_offset: i32,
+}
+
+/// A mention in a comment. `start` and `end` are character offsets of `@username`.
+#[derive(Serialize, Deserialize, Hash, PartialEq, Debug)]
+pub struct MentionSpan {
+    pub start: i32,
+    pub end: i32,
+    pub user_id: i32,
+    pub user_name: String,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Finds `@username` in `content`, returning the character offsets of each and the name.
+/// Underscores in names stand for spaces, as in wiki links.
+pub fn parse_mentions(content: &str) -> Vec<(usize, usize, String)> {
+    let chars: Vec<char> = content.chars().collect();
+    let mut mentions = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        // An `@` right after a name character is part of something else, like an email address
+        if chars[i] != '@' || (i > 0 && is_name_char(chars[i - 1])) {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 1;
+        while end < chars.len() && is_name_char(chars[end]) {
+            end += 1;
+        }
+        while end > i + 1 && (chars[end - 1] == '.' || chars[end - 1] == '-') {
+            end -= 1;
+        }
+        let name = chars[i + 1..end]
+            .iter()
+            .map(|c| if *c == '_' { ' ' } else { *c })
+            .collect::<String>();
+        let name = name.trim();
+        if !name.is_empty() && name.chars().count() <= MAX_NAME_LENGTH {
+            mentions.push((i, end, name.to_owned()));
+        }
+        i = end.max(i + 1);
+    }
+    mentions
+}
+
+impl CommentMention {
+    /// Resolves user names against the authors of comments, ignoring case.
+    /// Returns the id and the stored name of each user found.
+    pub fn resolve(
+        conn: &MysqlConnection,
+        names: &[String],
+    ) -> Result<HashMap<String, (i32, String)>> {
+        let authors = comments::table
+            .filter(comments::author_name.eq_any(names))
+            .filter(comments::author_id.is_not_null())
+            .select((comments::author_id, comments::author_name))
+            .distinct()
+            .load::<(Option<i32>, Option<String>)>(conn)?;
+        let mut resolved = HashMap::new();
+        for (id, name) in authors {
+            if let (Some(id), Some(name)) = (id, name) {
+                resolved.insert(name.to_lowercase(), (id, name));
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Stores the mentions of known users in the comment, replacing any previous ones.
+    /// Returns the ids of the mentioned users.
+    pub fn refresh(conn: &MysqlConnection, comment: &Comment) -> Result<Vec<i32>> {
+        diesel::delete(comment_mentions::table.filter(comment_mentions::comment_id.eq(comment.id)))
+            .execute(conn)?;
+        let mentions = parse_mentions(&comment.content);
+        if mentions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let names = mentions
+            .iter()
+            .map(|(_, _, name)| name.clone())
+            .collect::<Vec<String>>();
+        let users = Self::resolve(conn, &names)?;
+        let new_mentions = mentions
+            .iter()
+            .filter_map(|(start, end, name)| {
+                users
+                    .get(&name.to_lowercase())
+                    .map(|(user_id, user_name)| NewCommentMention {
+                        comment_id: comment.id,
+                        user_id: *user_id,
+                        user_name,
+                        start_offset: *start as i32,
+                        end_offset: *end as i32,
+                    })
+            })
+            .collect::<Vec<NewCommentMention>>();
+        if new_mentions.is_empty() {
+            return Ok(Vec::new());
+        }
+        diesel::insert_into(comment_mentions::table)
+            .values(&new_mentions)
+            .execute(conn)?;
+        let mut user_ids = new_mentions.iter().map(|x| x.user_id).collect::<Vec<i32>>();
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        Ok(user_ids)
+    }
+
+    /// Returns the mentions in each of `comment_ids`. Comments without mentions are left out.
+    pub fn get_spans(
+        conn: &MysqlConnection,
+        comment_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<MentionSpan>>> {
+        let mentions = comment_mentions::table
+            .filter(comment_mentions::comment_id.eq_any(comment_ids))
+            .order_by(comment_mentions::start_offset.asc())
+            .load::<Self>(conn)?;
+        let mut spans: HashMap<i32, Vec<MentionSpan>> = HashMap::new();
+        for x in mentions {
+            spans.entry(x.comment_id).or_default().push(MentionSpan {
+                start: x.start_offset,
+                end: x.end_offset,
+                user_id: x.user_id,
+                user_name: x.user_name,
+            });
+        }
+        Ok(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mentions() {
+        assert_eq!(
+            vec![
+                (0, 6, "Admin".to_owned()),
+                (10, 17, "리브레 위키".to_owned()),
+                (19, 25, "a.b-c".to_owned()),
+            ],
+            parse_mentions("@Admin 님, @리브레_위키: @a.b-c. me@example.com @ @_")
+        );
+    }
+}
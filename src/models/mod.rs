@@ -1,11 +1,40 @@
+mod ban;
 mod board;
 mod comment;
+mod comment_revision;
+mod feed;
+mod ip;
 mod log;
+mod mention;
+mod moderation;
+mod notification;
+mod permission;
+mod pseudonym;
+mod reaction;
+mod report;
+mod search;
 mod topic;
-pub use self::log::{Log, LogContent, LogType};
-pub use board::{Board, BoardPublic};
+mod topic_watch;
+pub use self::log::{Log, LogContent, LogFilter, LogPublic, LogType};
+pub use ban::{Ban, BanPublic, IpRange};
+pub use board::{Board, BoardForm, BoardPublic, TopicOrder};
 pub use comment::{Comment, CommentForm, CommentPublic};
+pub use comment_revision::{CommentRevision, CommentRevisionPublic};
+pub use feed::{Feed, FeedEntry, FEED_SIZE};
+pub use ip::{display_ip, ip_from_bytes, IpDisplay, IP_DISPLAY, IP_HASH_KEY};
+pub use mention::{parse_mentions, CommentMention, MentionSpan};
+pub use moderation::ContentFilter;
+pub use notification::{Notification, NotificationKind, NotificationPublic};
+pub use permission::{Permission, PermissionGrant, Permissions};
+pub use pseudonym::{pseudonym, PSEUDONYM_KEY};
+pub use reaction::{CommentReaction, ReactionCount, REACTION_EMOJIS};
+pub use report::{Report, ReportGroup, ReportPublic, ReportReason, ReportStatus, ReportTarget};
+pub use search::{
+    escape_html, highlight, parse_terms, to_boolean_query, CommentSearchResult, SearchFilter,
+    TopicSearchResult,
+};
 pub use topic::{Topic, TopicForm, TopicPublic};
+pub use topic_watch::TopicWatch;
 
 use actix_web::{
     http::header::{ETag, EntityTag, IF_NONE_MATCH},
@@ -26,6 +55,15 @@ fn etag_equals(request: &HttpRequest, etag: &str) -> bool {
 pub trait PublicEntity {
     fn get_etag(&self) -> String;
     fn cache_response(&self, request: &HttpRequest) -> HttpResponse;
+    /// Like `cache_response`, with the body rendered by `render` instead of as JSON.
+    fn cache_response_with<F>(
+        &self,
+        request: &HttpRequest,
+        content_type: &str,
+        render: F,
+    ) -> HttpResponse
+    where
+        F: FnOnce(&Self) -> String;
 }
 
 impl<T> PublicEntity for T
@@ -48,4 +86,23 @@ where
                 .json(&self)
         }
     }
+    fn cache_response_with<F>(
+        &self,
+        request: &HttpRequest,
+        content_type: &str,
+        render: F,
+    ) -> HttpResponse
+    where
+        F: FnOnce(&Self) -> String,
+    {
+        let etag = self.get_etag();
+        if etag_equals(request, &etag) {
+            HttpResponse::NotModified().finish()
+        } else {
+            HttpResponse::Ok()
+                .set(ETag(EntityTag::strong(etag)))
+                .content_type(content_type)
+                .body(render(self))
+        }
+    }
 }
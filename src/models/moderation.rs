@@ -0,0 +1,281 @@
+use crate::models::{Comment, IpRange, Topic};
+use crate::schema::{comments, topics};
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::dsl::{sql, InnerJoin, IntoBoxed};
+use diesel::mysql::Mysql;
+use diesel::prelude::*;
+use diesel::sql_types::{Bool, Integer};
+
+/// Topics and comments changed by bulk moderation, the comments with their topics.
+type Changed = (Vec<Topic>, Vec<(Comment, Topic)>);
+
+/// Selects topics and comments for bulk moderation.
+/// Nothing matches unless `author_id` or `ip_range` is set.
+#[derive(Default, Debug)]
+pub struct ContentFilter {
+    pub board_id: Option<i32>,
+    pub author_id: Option<i32>,
+    pub ip_range: Option<IpRange>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+impl ContentFilter {
+    fn is_empty(&self) -> bool {
+        self.author_id.is_none() && self.ip_range.is_none()
+    }
+
+    fn topics_query(&self, is_hidden: bool) -> topics::BoxedQuery<'static, Mysql> {
+        let mut query = topics::table
+            .filter(topics::is_hidden.ne(is_hidden))
+            .into_boxed();
+        if let Some(board_id) = self.board_id {
+            query = query.filter(topics::board_id.eq(board_id));
+        }
+        if let Some(author_id) = self.author_id {
+            query = query.filter(topics::author_id.eq(author_id));
+        }
+        if let Some(ip_range) = &self.ip_range {
+            query = query
+                .filter(topics::author_ip.between(ip_range.start.clone(), ip_range.end.clone()))
+                .filter(
+                    sql::<Bool>("LENGTH(topics.author_ip) = ")
+                        .bind::<Integer, _>(ip_range.start.len() as i32),
+                );
+        }
+        if let Some(from) = self.from {
+            query = query.filter(topics::created_at.ge(from));
+        }
+        if let Some(to) = self.to {
+            query = query.filter(topics::created_at.lt(to));
+        }
+        query
+    }
+
+    fn comments_query(
+        &self,
+        is_hidden: bool,
+    ) -> IntoBoxed<'static, InnerJoin<comments::table, topics::table>, Mysql> {
+        let mut query = comments::table
+            .inner_join(topics::table)
+            .filter(comments::is_hidden.ne(is_hidden))
+            .into_boxed();
+        if let Some(board_id) = self.board_id {
+            query = query.filter(topics::board_id.eq(board_id));
+        }
+        if let Some(author_id) = self.author_id {
+            query = query.filter(comments::author_id.eq(author_id));
+        }
+        if let Some(ip_range) = &self.ip_range {
+            query = query
+                .filter(comments::author_ip.between(ip_range.start.clone(), ip_range.end.clone()))
+                .filter(
+                    sql::<Bool>("LENGTH(comments.author_ip) = ")
+                        .bind::<Integer, _>(ip_range.start.len() as i32),
+                );
+        }
+        if let Some(from) = self.from {
+            query = query.filter(comments::created_at.ge(from));
+        }
+        if let Some(to) = self.to {
+            query = query.filter(comments::created_at.lt(to));
+        }
+        query
+    }
+
+    /// Returns matching topics that are not yet in the given state.
+    pub fn find_topics(&self, conn: &MysqlConnection, is_hidden: bool) -> Result<Vec<Topic>> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let topics = self
+            .topics_query(is_hidden)
+            .order_by(topics::id)
+            .load::<Topic>(conn)?;
+        Ok(topics)
+    }
+
+    /// Counts matching topics that are not yet in the given state.
+    pub fn count_topics(&self, conn: &MysqlConnection, is_hidden: bool) -> Result<i64> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+        let count = self
+            .topics_query(is_hidden)
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(count)
+    }
+
+    /// Returns matching comments that are not yet in the given state, with their topics.
+    pub fn find_comments(
+        &self,
+        conn: &MysqlConnection,
+        is_hidden: bool,
+    ) -> Result<Vec<(Comment, Topic)>> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let comments = self
+            .comments_query(is_hidden)
+            .order_by(comments::id)
+            .load::<(Comment, Topic)>(conn)?;
+        Ok(comments)
+    }
+
+    /// Counts matching comments that are not yet in the given state.
+    pub fn count_comments(&self, conn: &MysqlConnection, is_hidden: bool) -> Result<i64> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+        let count = self
+            .comments_query(is_hidden)
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(count)
+    }
+
+    /// Hides or unhides every match and returns the changed topics and comments.
+    /// Topics are changed first, so that the topics of changed comments are up to date.
+    pub fn set_hidden(&self, conn: &MysqlConnection, is_hidden: bool) -> Result<Changed> {
+        let topic_ids = self
+            .find_topics(conn, is_hidden)?
+            .iter()
+            .map(|x| x.id)
+            .collect::<Vec<i32>>();
+        diesel::update(topics::table.filter(topics::id.eq_any(&topic_ids)))
+            .set(topics::is_hidden.eq(is_hidden))
+            .execute(conn)?;
+        let comment_ids = self
+            .find_comments(conn, is_hidden)?
+            .iter()
+            .map(|(x, _)| x.id)
+            .collect::<Vec<i32>>();
+        diesel::update(comments::table.filter(comments::id.eq_any(&comment_ids)))
+            .set(comments::is_hidden.eq(is_hidden))
+            .execute(conn)?;
+
+        let topics = topics::table
+            .filter(topics::id.eq_any(&topic_ids))
+            .order_by(topics::id)
+            .load::<Topic>(conn)?;
+        let comments = comments::table
+            .inner_join(topics::table)
+            .filter(comments::id.eq_any(&comment_ids))
+            .order_by(comments::id)
+            .load::<(Comment, Topic)>(conn)?;
+        Ok((topics, comments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::models::Board;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_content_filter() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = Board::create(&conn, "일괄테스트", "test_bulk").expect("must succeed");
+            let spammer_ip = IpAddr::from_str("192.0.2.3").expect("must succeed");
+            let other_ip = IpAddr::from_str("198.51.100.4").expect("must succeed");
+            let topic = Topic::create(
+                &conn,
+                &board,
+                "test title",
+                Some(3),
+                Some("test_spammer"),
+                &spammer_ip,
+            )
+            .expect("must succeed");
+            Comment::create(&conn, &topic, None, "a", None, None, &spammer_ip)
+                .expect("must succeed");
+            Comment::create(&conn, &topic, None, "b", Some(4), Some("test"), &other_ip)
+                .expect("must succeed");
+
+            let filter = ContentFilter {
+                board_id: Some(board.id),
+                ip_range: Some(IpRange::from_str("192.0.2.0/24").expect("must succeed")),
+                ..Default::default()
+            };
+            assert_eq!(
+                1,
+                filter.find_topics(&conn, true).expect("must succeed").len()
+            );
+            assert_eq!(
+                1,
+                filter
+                    .find_comments(&conn, true)
+                    .expect("must succeed")
+                    .len()
+            );
+            assert_eq!(1, filter.count_topics(&conn, true).expect("must succeed"));
+            assert_eq!(1, filter.count_comments(&conn, true).expect("must succeed"));
+
+            let filter = ContentFilter {
+                board_id: Some(board.id),
+                author_id: Some(4),
+                ..Default::default()
+            };
+            assert_eq!(
+                0,
+                filter.find_topics(&conn, true).expect("must succeed").len()
+            );
+            assert_eq!(
+                1,
+                filter
+                    .find_comments(&conn, true)
+                    .expect("must succeed")
+                    .len()
+            );
+
+            let filter = ContentFilter {
+                board_id: Some(board.id),
+                ip_range: Some(IpRange::from_str("192.0.2.3").expect("must succeed")),
+                ..Default::default()
+            };
+            let (topics, comments) = filter.set_hidden(&conn, true).expect("must succeed");
+            assert_eq!(1, topics.len());
+            assert_eq!(true, topics[0].is_hidden);
+            assert_eq!(1, comments.len());
+            assert_eq!(true, comments[0].0.is_hidden);
+            assert_eq!(true, comments[0].1.is_hidden);
+            assert_eq!(
+                0,
+                filter.find_topics(&conn, true).expect("must succeed").len()
+            );
+            assert_eq!(
+                0,
+                filter
+                    .find_comments(&conn, true)
+                    .expect("must succeed")
+                    .len()
+            );
+            assert_eq!(
+                1,
+                filter
+                    .find_comments(&conn, false)
+                    .expect("must succeed")
+                    .len()
+            );
+
+            let filter = ContentFilter {
+                board_id: Some(board.id),
+                ..Default::default()
+            };
+            assert_eq!(
+                0,
+                filter
+                    .find_topics(&conn, false)
+                    .expect("must succeed")
+                    .len()
+            );
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,275 @@
+use crate::models::{Comment, Topic, TopicWatch};
+use crate::schema::{comments, notifications, topics};
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// A comment on a watched topic.
+    Comment,
+    /// A comment mentioning the user.
+    Mention,
+}
+
+impl NotificationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::Comment => "comment",
+            NotificationKind::Mention => "mention",
+        }
+    }
+}
+
+impl FromStr for NotificationKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "comment" => Ok(NotificationKind::Comment),
+            "mention" => Ok(NotificationKind::Mention),
+            _ => Err(anyhow::anyhow!("Unknown notification kind: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+pub struct Notification {
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: String,
+    pub topic_id: i32,
+    pub comment_id: i32,
+    pub is_read: bool,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "notifications"]
+struct NewNotification<'a> {
+    pub user_id: i32,
+    pub kind: &'a str,
+    pub topic_id: i32,
+    pub comment_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NotificationPublic {
+    pub id: i32,
+    pub kind: Option<NotificationKind>,
+    pub topic_id: i32,
+    pub topic_title: String,
+    pub comment_id: i32,
+    pub author_name: String,
+    pub is_read: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Notification {
+    /// Notifies `user_ids` of a comment, skipping its author.
+    pub fn create_many(
+        conn: &MysqlConnection,
+        kind: NotificationKind,
+        comment: &Comment,
+        user_ids: &[i32],
+    ) -> Result<()> {
+        let new_notifications = user_ids
+            .iter()
+            .filter(|x| comment.author_id != Some(**x))
+            .map(|x| NewNotification {
+                user_id: *x,
+                kind: kind.as_str(),
+                topic_id: comment.topic_id,
+                comment_id: comment.id,
+            })
+            .collect::<Vec<NewNotification>>();
+        if new_notifications.is_empty() {
+            return Ok(());
+        }
+        diesel::insert_into(notifications::table)
+            .values(&new_notifications)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Notifies the watchers of the comment's topic, except `except`.
+    pub fn notify_watchers(
+        conn: &MysqlConnection,
+        comment: &Comment,
+        except: &[i32],
+    ) -> Result<()> {
+        let watchers = TopicWatch::get_watchers(conn, comment.topic_id)?
+            .into_iter()
+            .filter(|x| !except.contains(x))
+            .collect::<Vec<i32>>();
+        Self::create_many(conn, NotificationKind::Comment, comment, &watchers)
+    }
+
+    /// Returns a user's notifications, newest first.
+    /// Those on hidden topics or comments are left out.
+    pub fn get_all(
+        conn: &MysqlConnection,
+        user_id: i32,
+        unread_only: bool,
+        before: Option<i32>,
+        limit: i32,
+    ) -> Result<Vec<(Self, Topic, Comment)>> {
+        let mut query = notifications::table
+            .inner_join(topics::table)
+            .inner_join(comments::table)
+            .filter(notifications::user_id.eq(user_id))
+            .filter(topics::is_hidden.eq(false))
+            .filter(comments::is_hidden.eq(false))
+            .into_boxed();
+        if unread_only {
+            query = query.filter(notifications::is_read.eq(false));
+        }
+        if let Some(before) = before {
+            query = query.filter(notifications::id.lt(before));
+        }
+        let results = query
+            .order_by(notifications::id.desc())
+            .limit(limit.into())
+            .load::<(Self, Topic, Comment)>(conn)?;
+        Ok(results)
+    }
+
+    pub fn count_unread(conn: &MysqlConnection, user_id: i32) -> Result<i64> {
+        let count = notifications::table
+            .inner_join(topics::table)
+            .inner_join(comments::table)
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::is_read.eq(false))
+            .filter(topics::is_hidden.eq(false))
+            .filter(comments::is_hidden.eq(false))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(count)
+    }
+
+    /// Marks the given notifications of a user as read, or all of them if `ids` is `None`.
+    pub fn mark_read(conn: &MysqlConnection, user_id: i32, ids: Option<&[i32]>) -> Result<()> {
+        let target = notifications::table
+            .filter(notifications::user_id.eq(user_id))
+            .filter(notifications::is_read.eq(false));
+        match ids {
+            Some(ids) => diesel::update(target.filter(notifications::id.eq_any(ids)))
+                .set(notifications::is_read.eq(true))
+                .execute(conn)?,
+            None => diesel::update(target)
+                .set(notifications::is_read.eq(true))
+                .execute(conn)?,
+        };
+        Ok(())
+    }
+
+    pub fn get_public(&self, topic: &Topic, comment: &Comment) -> NotificationPublic {
+        NotificationPublic {
+            id: self.id,
+            kind: NotificationKind::from_str(&self.kind).ok(),
+            topic_id: self.topic_id,
+            topic_title: topic.title.clone(),
+            comment_id: self.comment_id,
+            author_name: comment.get_public(false).author_name,
+            is_read: self.is_read,
+            created_at: DateTime::<Utc>::from_utc(self.created_at, Utc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::models::{Board, CommentForm, MentionSpan, IP_DISPLAY};
+    use std::net::IpAddr;
+
+    #[test]
+    fn test_notification() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let boards = Board::get_all(&conn).expect("A board must exist");
+            let ip = IpAddr::from_str("192.0.2.1").expect("must succeed");
+            let topic = Topic::create(&conn, &boards[0], "test title", Some(3), Some("a"), &ip)
+                .expect("must succeed");
+            let first = Comment::create(&conn, &topic, None, "First", Some(3), Some("a"), &ip)
+                .expect("must succeed");
+            assert_eq!(
+                vec![3],
+                TopicWatch::get_watchers(&conn, topic.id).expect("must succeed")
+            );
+            assert_eq!(
+                0,
+                Notification::count_unread(&conn, 3).expect("must succeed")
+            );
+
+            let reply = Comment::create(
+                &conn,
+                &topic,
+                Some(&first),
+                "Reply",
+                Some(4),
+                Some("b"),
+                &ip,
+            )
+            .expect("must succeed");
+            Comment::create(&conn, &topic, None, "Anonymous", None, None, &ip)
+                .expect("must succeed");
+            assert_eq!(
+                2,
+                Notification::count_unread(&conn, 3).expect("must succeed")
+            );
+            assert_eq!(
+                1,
+                Notification::count_unread(&conn, 4).expect("must succeed")
+            );
+
+            CommentForm {
+                id: reply.id,
+                is_hidden: Some(true),
+            }
+            .save(&conn)
+            .expect("must succeed");
+            let notifications =
+                Notification::get_all(&conn, 3, true, None, 10).expect("must succeed");
+            assert_eq!(1, notifications.len());
+            let (notification, notified_topic, comment) = &notifications[0];
+            let public = notification.get_public(notified_topic, comment);
+            assert_eq!(Some(NotificationKind::Comment), public.kind);
+            assert_eq!(IP_DISPLAY.format(&ip), public.author_name);
+
+            Notification::mark_read(&conn, 3, Some(&[notification.id])).expect("must succeed");
+            assert_eq!(
+                0,
+                Notification::count_unread(&conn, 3).expect("must succeed")
+            );
+            Notification::mark_read(&conn, 4, None).expect("must succeed");
+            assert_eq!(
+                0,
+                Notification::count_unread(&conn, 4).expect("must succeed")
+            );
+
+            // A mentioned watcher is notified once
+            let mention = Comment::create(&conn, &topic, None, "@A hi", Some(4), Some("b"), &ip)
+                .expect("must succeed");
+            let notifications =
+                Notification::get_all(&conn, 3, true, None, 10).expect("must succeed");
+            assert_eq!(1, notifications.len());
+            assert_eq!(mention.id, notifications[0].0.comment_id);
+            assert_eq!("mention", notifications[0].0.kind);
+            let public = Comment::get_public_list(&conn, &[mention], false).expect("must succeed");
+            assert_eq!(
+                vec![MentionSpan {
+                    start: 0,
+                    end: 2,
+                    user_id: 3,
+                    user_name: "a".to_owned(),
+                }],
+                public[0].mentions
+            );
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,116 @@
+use crate::auth::Profile;
+use crate::schema::permission_grants;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    CloseTopic,
+    SuspendTopic,
+    HideTopic,
+    PinTopic,
+    EditTopic,
+    HideComment,
+    EditComment,
+    ViewHidden,
+    ViewIp,
+    ManageBoards,
+    ManageUsers,
+    ManageBans,
+    ManageReports,
+}
+
+impl FromStr for Permission {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "close_topic" => Ok(Permission::CloseTopic),
+            "suspend_topic" => Ok(Permission::SuspendTopic),
+            "hide_topic" => Ok(Permission::HideTopic),
+            "pin_topic" => Ok(Permission::PinTopic),
+            "edit_topic" => Ok(Permission::EditTopic),
+            "hide_comment" => Ok(Permission::HideComment),
+            "edit_comment" => Ok(Permission::EditComment),
+            "view_hidden" => Ok(Permission::ViewHidden),
+            "view_ip" => Ok(Permission::ViewIp),
+            "manage_boards" => Ok(Permission::ManageBoards),
+            "manage_users" => Ok(Permission::ManageUsers),
+            "manage_bans" => Ok(Permission::ManageBans),
+            "manage_reports" => Ok(Permission::ManageReports),
+            _ => Err(anyhow::anyhow!("Unknown permission: {}", s)),
+        }
+    }
+}
+
+/// Grants a permission to everyone in a wiki group or holding a wiki right.
+/// A grant with a `board_id` only applies to topics and comments on that board.
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+pub struct PermissionGrant {
+    pub id: i32,
+    pub permission: String,
+    pub wiki_group: Option<String>,
+    pub wiki_right: Option<String>,
+    pub board_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug)]
+pub struct Permissions {
+    grants: Vec<(Permission, Option<i32>)>,
+}
+
+impl Permissions {
+    pub fn get(conn: &MysqlConnection, profile: &Profile) -> Result<Self> {
+        if profile.blocked {
+            return Ok(Permissions { grants: vec![] });
+        }
+        let grants = permission_grants::table
+            .filter(
+                permission_grants::wiki_group
+                    .eq_any(&profile.groups)
+                    .or(permission_grants::wiki_right.eq_any(&profile.rights)),
+            )
+            .load::<PermissionGrant>(conn)?;
+        let grants = grants
+            .iter()
+            .filter_map(|x| match Permission::from_str(&x.permission) {
+                Ok(permission) => Some((permission, x.board_id)),
+                Err(_) => None,
+            })
+            .collect();
+        Ok(Permissions { grants })
+    }
+
+    /// Checks a permission on a board. Pass `None` for actions which
+    /// do not belong to a board; only site-wide grants apply then.
+    pub fn has(&self, permission: Permission, board_id: Option<i32>) -> bool {
+        self.grants
+            .iter()
+            .any(|(p, b)| *p == permission && (b.is_none() || *b == board_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has() {
+        let permissions = Permissions {
+            grants: vec![
+                (Permission::HideComment, None),
+                (Permission::PinTopic, Some(5)),
+            ],
+        };
+        assert_eq!(true, permissions.has(Permission::HideComment, None));
+        assert_eq!(true, permissions.has(Permission::HideComment, Some(1)));
+        assert_eq!(true, permissions.has(Permission::PinTopic, Some(5)));
+        assert_eq!(false, permissions.has(Permission::PinTopic, Some(1)));
+        assert_eq!(false, permissions.has(Permission::PinTopic, None));
+        assert_eq!(false, permissions.has(Permission::ViewHidden, Some(5)));
+    }
+}
@@ -0,0 +1,61 @@
+use lazy_static::lazy_static;
+use ring::hmac;
+use std::env;
+use std::net::IpAddr;
+
+lazy_static! {
+    pub static ref PSEUDONYM_KEY: hmac::Key = hmac::Key::new(
+        hmac::HMAC_SHA256,
+        env::var("PSEUDONYM_SECRET")
+            .expect("PSEUDONYM_SECRET is not set")
+            .as_bytes(),
+    );
+}
+
+fn pseudonym_with_key(key: &hmac::Key, topic_id: i32, user_id: Option<i32>, ip: &IpAddr) -> String {
+    let identity = match user_id {
+        Some(user_id) => format!("u{}", user_id),
+        None => match ip {
+            IpAddr::V4(ip) => ip.octets().iter().map(|x| format!("{:02X}", x)).collect(),
+            IpAddr::V6(ip) => ip.octets().iter().map(|x| format!("{:02X}", x)).collect(),
+        },
+    };
+    let tag = hmac::sign(key, format!("{}:{}", topic_id, identity).as_bytes());
+    let hex = tag.as_ref()[..2]
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>();
+    format!("익명-{}", hex)
+}
+
+/// Returns the name an author goes by within a topic on an anonymous board.
+/// Logged-in users are told apart by their id, others by their IP.
+pub fn pseudonym(topic_id: i32, user_id: Option<i32>, ip: &IpAddr) -> String {
+    pseudonym_with_key(&PSEUDONYM_KEY, topic_id, user_id, ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_pseudonym() {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"test secret");
+        let ip = IpAddr::from_str("192.0.2.1").expect("must succeed");
+        let other_ip = IpAddr::from_str("192.0.2.2").expect("must succeed");
+        let name = pseudonym_with_key(&key, 1, None, &ip);
+        assert_eq!(true, name.starts_with("익명-"));
+        assert_eq!(4, name.chars().count() - "익명-".chars().count());
+        assert_eq!(name, pseudonym_with_key(&key, 1, None, &ip));
+        // The same user keeps the name on another IP, but not in another topic
+        assert_eq!(
+            pseudonym_with_key(&key, 1, Some(3), &ip),
+            pseudonym_with_key(&key, 1, Some(3), &other_ip)
+        );
+        assert_ne!(
+            pseudonym_with_key(&key, 1, Some(3), &ip),
+            pseudonym_with_key(&key, 2, Some(3), &ip)
+        );
+    }
+}
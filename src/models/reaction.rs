@@ -0,0 +1,212 @@
+use crate::schema::comment_reactions;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::dsl::sql;
+use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::net::IpAddr;
+
+const DEFAULT_EMOJIS: &[&str] = &["👍", "❤️", "😂", "😮", "😢", "😡"];
+
+lazy_static! {
+    /// Emojis allowed as reactions, in display order.
+    /// Set `REACTION_EMOJIS` to a comma separated list to override the default set.
+    pub static ref REACTION_EMOJIS: Vec<String> = match env::var("REACTION_EMOJIS") {
+        Ok(value) => value
+            .split(',')
+            .map(|x| x.trim().to_owned())
+            .filter(|x| !x.is_empty())
+            .collect(),
+        Err(_) => DEFAULT_EMOJIS.iter().map(|x| (*x).to_owned()).collect(),
+    };
+}
+
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+pub struct CommentReaction {
+    pub id: i32,
+    pub comment_id: i32,
+    pub emoji: String,
+    pub user_id: Option<i32>,
+    pub user_ip: Vec<u8>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "comment_reactions"]
+struct NewCommentReaction<'a> {
+    pub comment_id: i32,
+    pub emoji: &'a str,
+    pub user_id: Option<i32>,
+    pub user_ip: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize, Hash, PartialEq, Debug)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: i64,
+}
+
+impl CommentReaction {
+    pub fn is_allowed(emoji: &str) -> bool {
+        REACTION_EMOJIS.iter().any(|x| x == emoji)
+    }
+
+    /// Adds a reaction. Logged-in users are identified by `user_id`, anonymous ones by `user_ip`,
+    /// and reacting twice with the same emoji does nothing.
+    pub fn add(
+        conn: &MysqlConnection,
+        comment_id: i32,
+        emoji: &str,
+        user_id: Option<i32>,
+        user_ip: &IpAddr,
+    ) -> Result<()> {
+        let ip_bin: Vec<u8> = match &user_ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        let new_reaction = NewCommentReaction {
+            comment_id,
+            emoji,
+            user_id,
+            user_ip: ip_bin,
+        };
+        diesel::insert_or_ignore_into(comment_reactions::table)
+            .values(new_reaction)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Removes a reaction added by `add` with the same arguments, if any.
+    pub fn remove(
+        conn: &MysqlConnection,
+        comment_id: i32,
+        emoji: &str,
+        user_id: Option<i32>,
+        user_ip: &IpAddr,
+    ) -> Result<()> {
+        let reactions = comment_reactions::table
+            .filter(comment_reactions::comment_id.eq(comment_id))
+            .filter(comment_reactions::emoji.eq(emoji));
+        match user_id {
+            Some(user_id) => {
+                diesel::delete(reactions.filter(comment_reactions::user_id.eq(user_id)))
+                    .execute(conn)?
+            }
+            None => {
+                let ip_bin: Vec<u8> = match &user_ip {
+                    IpAddr::V4(ip) => ip.octets().to_vec(),
+                    IpAddr::V6(ip) => ip.octets().to_vec(),
+                };
+                diesel::delete(
+                    reactions
+                        .filter(comment_reactions::user_id.is_null())
+                        .filter(comment_reactions::user_ip.eq(ip_bin)),
+                )
+                .execute(conn)?
+            }
+        };
+        Ok(())
+    }
+
+    /// Counts the reactions to each of `comment_ids`, ordered like `REACTION_EMOJIS`.
+    /// Comments without reactions are left out.
+    pub fn get_counts(
+        conn: &MysqlConnection,
+        comment_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<ReactionCount>>> {
+        let rows = comment_reactions::table
+            .filter(comment_reactions::comment_id.eq_any(comment_ids))
+            .group_by((comment_reactions::comment_id, comment_reactions::emoji))
+            .select((
+                comment_reactions::comment_id,
+                comment_reactions::emoji,
+                sql::<BigInt>("COUNT(*)"),
+            ))
+            .load::<(i32, String, i64)>(conn)?;
+        let mut counts: HashMap<i32, Vec<ReactionCount>> = HashMap::new();
+        for (comment_id, emoji, count) in rows {
+            counts
+                .entry(comment_id)
+                .or_default()
+                .push(ReactionCount { emoji, count });
+        }
+        for reactions in counts.values_mut() {
+            // Emojis removed from the configuration still count, after the others
+            reactions.sort_by_key(|x| {
+                REACTION_EMOJIS
+                    .iter()
+                    .position(|e| *e == x.emoji)
+                    .unwrap_or(usize::MAX)
+            });
+        }
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::models::{Board, Comment, Topic};
+
+    #[test]
+    fn test_reaction() {
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let boards = Board::get_all(&conn).expect("A board must exist");
+            let ip = IpAddr::from_str("192.0.2.1").expect("must succeed");
+            let other_ip = IpAddr::from_str("192.0.2.2").expect("must succeed");
+            let topic = Topic::create(&conn, &boards[0], "test title", None, None, &ip)
+                .expect("must succeed");
+            let comment = Comment::create(&conn, &topic, None, "Test content", None, None, &ip)
+                .expect("must succeed");
+            let like = &REACTION_EMOJIS[0];
+            let love = &REACTION_EMOJIS[1];
+            assert_eq!(true, CommentReaction::is_allowed(like));
+            assert_eq!(false, CommentReaction::is_allowed("x"));
+
+            CommentReaction::add(&conn, comment.id, love, Some(3), &ip).expect("must succeed");
+            CommentReaction::add(&conn, comment.id, like, Some(3), &ip).expect("must succeed");
+            CommentReaction::add(&conn, comment.id, like, Some(3), &other_ip)
+                .expect("must succeed");
+            CommentReaction::add(&conn, comment.id, like, None, &ip).expect("must succeed");
+            CommentReaction::add(&conn, comment.id, like, None, &ip).expect("must succeed");
+            CommentReaction::add(&conn, comment.id, like, None, &other_ip).expect("must succeed");
+            let counts = CommentReaction::get_counts(&conn, &[comment.id]).expect("must succeed");
+            assert_eq!(
+                vec![
+                    ReactionCount {
+                        emoji: like.clone(),
+                        count: 3
+                    },
+                    ReactionCount {
+                        emoji: love.clone(),
+                        count: 1
+                    },
+                ],
+                counts[&comment.id]
+            );
+
+            CommentReaction::remove(&conn, comment.id, like, None, &ip).expect("must succeed");
+            CommentReaction::remove(&conn, comment.id, love, Some(3), &other_ip)
+                .expect("must succeed");
+            let public = Comment::get_public_list(&conn, &[comment], false).expect("must succeed");
+            assert_eq!(
+                vec![(like.clone(), 2)],
+                public[0]
+                    .reactions
+                    .iter()
+                    .map(|x| (x.emoji.clone(), x.count))
+                    .collect::<Vec<(String, i64)>>()
+            );
+
+            let updated = Topic::find_by_id(&conn, topic.id).expect("must succeed");
+            assert_eq!(topic.updated_at, updated.updated_at);
+            Ok(())
+        });
+    }
+}
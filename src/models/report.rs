@@ -0,0 +1,379 @@
+use crate::schema::reports;
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable, Timestamp, Unsigned, Varchar};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportTarget {
+    Topic,
+    Comment,
+}
+
+impl ReportTarget {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportTarget::Topic => "topic",
+            ReportTarget::Comment => "comment",
+        }
+    }
+}
+
+impl FromStr for ReportTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "topic" => Ok(ReportTarget::Topic),
+            "comment" => Ok(ReportTarget::Comment),
+            _ => Err(anyhow::anyhow!("Unknown report target: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportReason {
+    Spam,
+    Abuse,
+    /// Personal information of someone else.
+    Privacy,
+    Illegal,
+    Other,
+}
+
+impl ReportReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportReason::Spam => "spam",
+            ReportReason::Abuse => "abuse",
+            ReportReason::Privacy => "privacy",
+            ReportReason::Illegal => "illegal",
+            ReportReason::Other => "other",
+        }
+    }
+}
+
+impl FromStr for ReportReason {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "spam" => Ok(ReportReason::Spam),
+            "abuse" => Ok(ReportReason::Abuse),
+            "privacy" => Ok(ReportReason::Privacy),
+            "illegal" => Ok(ReportReason::Illegal),
+            "other" => Ok(ReportReason::Other),
+            _ => Err(anyhow::anyhow!("Unknown report reason: {}", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    Open,
+    /// Action was taken on the target.
+    Resolved,
+    /// The target was found to be fine.
+    Dismissed,
+}
+
+impl ReportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportStatus::Open => "open",
+            ReportStatus::Resolved => "resolved",
+            ReportStatus::Dismissed => "dismissed",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+pub struct Report {
+    pub id: i32,
+    pub target_type: String,
+    pub target_id: i32,
+    pub reason: String,
+    pub content: String,
+    pub user_id: Option<i32>,
+    pub user_name: Option<String>,
+    pub user_ip: Vec<u8>,
+    pub status: String,
+    pub resolved_by: Option<i32>,
+    pub resolved_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "reports"]
+struct NewReport<'a> {
+    pub target_type: &'a str,
+    pub target_id: i32,
+    pub reason: &'a str,
+    pub content: &'a str,
+    pub user_id: Option<i32>,
+    pub user_name: Option<&'a str>,
+    pub user_ip: Vec<u8>,
+}
+
+/// A report, without the IP of the reporter.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReportPublic {
+    pub id: i32,
+    pub reason: Option<ReportReason>,
+    pub content: String,
+    pub user_id: Option<i32>,
+    pub user_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(QueryableByName)]
+struct GroupRow {
+    #[sql_type = "Varchar"]
+    target_type: String,
+    #[sql_type = "Integer"]
+    target_id: i32,
+    #[sql_type = "Integer"]
+    board_id: i32,
+    #[sql_type = "BigInt"]
+    report_count: i64,
+    #[sql_type = "Timestamp"]
+    last_reported_at: NaiveDateTime,
+}
+
+/// The open reports on a target, as listed in the queue.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReportGroup {
+    pub target_type: Option<ReportTarget>,
+    pub target_id: i32,
+    /// Current board of the target.
+    pub board_id: i32,
+    pub report_count: i64,
+    pub last_reported_at: DateTime<Utc>,
+    /// Oldest first.
+    pub reports: Vec<ReportPublic>,
+}
+
+impl Report {
+    /// Files a report. Returns `false` if the reporter already has an open report on the target.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        conn: &MysqlConnection,
+        target_type: ReportTarget,
+        target_id: i32,
+        reason: ReportReason,
+        content: &str,
+        user_id: Option<i32>,
+        user_name: Option<&str>,
+        user_ip: &IpAddr,
+    ) -> Result<bool> {
+        let ip_bin: Vec<u8> = match &user_ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        let new_report = NewReport {
+            target_type: target_type.as_str(),
+            target_id,
+            reason: reason.as_str(),
+            content,
+            user_id,
+            user_name,
+            user_ip: ip_bin,
+        };
+        let inserted = diesel::insert_or_ignore_into(reports::table)
+            .values(new_report)
+            .execute(conn)?;
+        Ok(inserted > 0)
+    }
+
+    /// Returns the open reports grouped by target, the most reported first.
+    /// Targets are counted on the board they are in now.
+    /// Pass `None` as `board_id` for every board.
+    pub fn get_open_groups(
+        conn: &MysqlConnection,
+        board_id: Option<i32>,
+        limit: u32,
+    ) -> Result<Vec<ReportGroup>> {
+        let rows = diesel::sql_query(
+            "SELECT reports.target_type, reports.target_id, topics.board_id, \
+             COUNT(*) AS report_count, MAX(reports.created_at) AS last_reported_at \
+             FROM reports \
+             LEFT JOIN comments ON reports.target_type = 'comment' AND comments.id = reports.target_id \
+             JOIN topics ON topics.id = IF(reports.target_type = 'topic', reports.target_id, comments.topic_id) \
+             WHERE reports.status = 'open' AND (? IS NULL OR topics.board_id = ?) \
+             GROUP BY reports.target_type, reports.target_id, topics.board_id \
+             ORDER BY report_count DESC, last_reported_at DESC \
+             LIMIT ?",
+        )
+        .bind::<Nullable<Integer>, _>(board_id)
+        .bind::<Nullable<Integer>, _>(board_id)
+        .bind::<Unsigned<Integer>, _>(limit)
+        .load::<GroupRow>(conn)?;
+
+        let ids_of = |target_type: ReportTarget| {
+            rows.iter()
+                .filter(|x| x.target_type == target_type.as_str())
+                .map(|x| x.target_id)
+                .collect::<Vec<i32>>()
+        };
+        let reports = reports::table
+            .filter(reports::status.eq(ReportStatus::Open.as_str()))
+            .filter(
+                reports::target_type
+                    .eq(ReportTarget::Topic.as_str())
+                    .and(reports::target_id.eq_any(ids_of(ReportTarget::Topic)))
+                    .or(reports::target_type
+                        .eq(ReportTarget::Comment.as_str())
+                        .and(reports::target_id.eq_any(ids_of(ReportTarget::Comment)))),
+            )
+            .order_by(reports::id.asc())
+            .load::<Self>(conn)?;
+        let mut reports_of: HashMap<(String, i32), Vec<ReportPublic>> = HashMap::new();
+        for report in reports {
+            reports_of
+                .entry((report.target_type.clone(), report.target_id))
+                .or_default()
+                .push(report.get_public());
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ReportGroup {
+                target_type: ReportTarget::from_str(&row.target_type).ok(),
+                target_id: row.target_id,
+                board_id: row.board_id,
+                report_count: row.report_count,
+                last_reported_at: DateTime::<Utc>::from_utc(row.last_reported_at, Utc),
+                reports: reports_of
+                    .remove(&(row.target_type, row.target_id))
+                    .unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    /// Closes the open reports on a target, returning how many there were.
+    pub fn close_all(
+        conn: &MysqlConnection,
+        target_type: ReportTarget,
+        target_id: i32,
+        status: ReportStatus,
+        resolved_by: i32,
+    ) -> Result<usize> {
+        let count = diesel::update(
+            reports::table
+                .filter(reports::target_type.eq(target_type.as_str()))
+                .filter(reports::target_id.eq(target_id))
+                .filter(reports::status.eq(ReportStatus::Open.as_str())),
+        )
+        .set((
+            reports::status.eq(status.as_str()),
+            reports::resolved_by.eq(resolved_by),
+            reports::resolved_at.eq(diesel::dsl::now.nullable()),
+        ))
+        .execute(conn)?;
+        Ok(count)
+    }
+
+    pub fn get_public(&self) -> ReportPublic {
+        ReportPublic {
+            id: self.id,
+            reason: ReportReason::from_str(&self.reason).ok(),
+            content: self.content.clone(),
+            user_id: self.user_id,
+            user_name: self.user_name.clone(),
+            created_at: DateTime::<Utc>::from_utc(self.created_at, Utc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::models::{Board, Comment, Topic, TopicForm};
+
+    #[test]
+    fn test_report() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = &Board::get_all(&conn).expect("A board must exist")[0];
+            let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+            let topic =
+                Topic::create(&conn, board, "test title", None, None, &ip).expect("must succeed");
+            let comment = Comment::create(&conn, &topic, None, "test", None, None, &ip)
+                .expect("must succeed");
+            let report = |user_id: Option<i32>, ip: &IpAddr| {
+                Report::create(
+                    &conn,
+                    ReportTarget::Comment,
+                    comment.id,
+                    ReportReason::Spam,
+                    "광고",
+                    user_id,
+                    user_id.map(|_| "test_user"),
+                    ip,
+                )
+                .expect("must succeed")
+            };
+            let other_ip = IpAddr::from_str("127.0.0.4").expect("must succeed");
+            assert_eq!(true, report(Some(3), &ip));
+            assert_eq!(false, report(Some(3), &other_ip));
+            assert_eq!(true, report(None, &ip));
+            assert_eq!(false, report(None, &ip));
+            assert_eq!(true, report(None, &other_ip));
+
+            let groups = Report::get_open_groups(&conn, Some(board.id), 100).expect("must succeed");
+            let group = groups
+                .iter()
+                .find(|x| x.target_type == Some(ReportTarget::Comment) && x.target_id == comment.id)
+                .expect("must exist");
+            assert_eq!(3, group.report_count);
+            assert_eq!(3, group.reports.len());
+            assert_eq!(Some(ReportReason::Spam), group.reports[0].reason);
+
+            // Reports follow the target to another board
+            let other = Board::create(&conn, "신고테스트", "test_report").expect("must succeed");
+            TopicForm {
+                id: topic.id,
+                board_id: Some(other.id),
+                title: None,
+                is_closed: None,
+                is_suspended: None,
+                is_hidden: None,
+                is_pinned: None,
+            }
+            .save(&conn)
+            .expect("must succeed");
+            let groups = Report::get_open_groups(&conn, Some(other.id), 100).expect("must succeed");
+            assert_eq!(1, groups.len());
+            assert_eq!(other.id, groups[0].board_id);
+            assert_eq!(comment.id, groups[0].target_id);
+
+            let closed = Report::close_all(
+                &conn,
+                ReportTarget::Comment,
+                comment.id,
+                ReportStatus::Dismissed,
+                5,
+            )
+            .expect("must succeed");
+            assert_eq!(3, closed);
+            let groups = Report::get_open_groups(&conn, Some(board.id), 100).expect("must succeed");
+            assert_eq!(
+                false,
+                groups
+                    .iter()
+                    .any(|x| x.target_id == comment.id
+                        && x.target_type == Some(ReportTarget::Comment))
+            );
+            // Reporters may report again once their reports are closed
+            assert_eq!(true, report(Some(3), &ip));
+            Ok(())
+        });
+    }
+}
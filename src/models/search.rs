@@ -0,0 +1,148 @@
+use crate::models::{CommentPublic, TopicPublic};
+use chrono::NaiveDateTime;
+
+/// Characters with a special meaning in MySQL boolean full-text queries.
+const OPERATORS: &[char] = &['+', '-', '<', '>', '(', ')', '~', '*', '"', '@'];
+
+/// Number of characters shown on each side of the first match in a snippet.
+const SNIPPET_CONTEXT: usize = 60;
+
+#[derive(Default, Debug)]
+pub struct SearchFilter {
+    pub board_id: Option<i32>,
+    pub author_id: Option<i32>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TopicSearchResult {
+    pub topic: TopicPublic,
+    pub snippet: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CommentSearchResult {
+    pub comment: CommentPublic,
+    pub topic: TopicPublic,
+    pub snippet: String,
+}
+
+/// Splits a user query into search terms, dropping full-text operators.
+pub fn parse_terms(query: &str) -> Vec<String> {
+    query
+        .split_whitespace()
+        .map(|x| {
+            x.chars()
+                .filter(|c| !OPERATORS.contains(c))
+                .collect::<String>()
+        })
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Builds a boolean mode query requiring every term.
+/// Each term is quoted so that the ngram parser matches it as a phrase.
+pub fn to_boolean_query(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|x| format!("+\"{}\"", x))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Appends `c` to `out`, escaped for HTML and XML text and attributes.
+pub fn escape_html(c: char, out: &mut String) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        '\'' => out.push_str("&#39;"),
+        _ => out.push(c),
+    }
+}
+
+/// Returns an HTML-escaped excerpt of `text` around the first matching term,
+/// with every match wrapped in `<mark>`.
+pub fn highlight(text: &str, terms: &[String]) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let terms: Vec<Vec<char>> = terms
+        .iter()
+        .map(|x| {
+            x.chars()
+                .map(|c| c.to_lowercase().next().unwrap_or(c))
+                .collect()
+        })
+        .collect();
+    let match_at = |i: usize| -> Option<usize> {
+        terms
+            .iter()
+            .filter(|t| !t.is_empty() && lower[i..].starts_with(t))
+            .map(|t| t.len())
+            .max()
+    };
+
+    let first = (0..chars.len()).find(|i| match_at(*i).is_some());
+    let start = first.map_or(0, |x| x.saturating_sub(SNIPPET_CONTEXT));
+    let end = first.map_or(SNIPPET_CONTEXT * 2, |x| x + SNIPPET_CONTEXT);
+    let end = end.min(chars.len());
+
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    let mut i = start;
+    while i < end {
+        if let Some(len) = match_at(i) {
+            let len = len.min(end - i);
+            out.push_str("<mark>");
+            for c in &chars[i..i + len] {
+                escape_html(*c, &mut out);
+            }
+            out.push_str("</mark>");
+            i += len;
+        } else {
+            escape_html(chars[i], &mut out);
+            i += 1;
+        }
+    }
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_terms() {
+        let terms = parse_terms(" 리브레 +위키  \"-\" (test)* ");
+        assert_eq!(vec!["리브레", "위키", "test"], terms);
+        assert_eq!(r#"+"리브레" +"위키" +"test""#, to_boolean_query(&terms));
+        assert_eq!(0, parse_terms("+-*").len());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let terms = vec!["위키".to_owned()];
+        assert_eq!(
+            "리브레<mark>위키</mark>는 &lt;자유&gt; <mark>위키</mark>",
+            highlight("리브레위키는 <자유> 위키", &terms)
+        );
+
+        let terms = vec!["rust".to_owned()];
+        let text = format!("{}Rust{}", "a".repeat(100), "b".repeat(100));
+        let snippet = highlight(&text, &terms);
+        assert_eq!(
+            format!("…{}<mark>Rust</mark>{}…", "a".repeat(60), "b".repeat(56)),
+            snippet
+        );
+    }
+}
@@ -1,13 +1,12 @@
-use crate::models::{Board, Comment};
-use crate::schema::{comments, topics};
+use crate::db::last_insert_id;
+use crate::models::{display_ip, pseudonym, Board, Comment, SearchFilter};
+use crate::schema::{boards, comments, topics};
 use anyhow::Result;
 use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::dsl::sql;
 use diesel::prelude::*;
-use std::{
-    convert::TryInto,
-    hash::Hash,
-    net::{IpAddr, Ipv4Addr, Ipv6Addr},
-};
+use diesel::sql_types::{Bool, Text};
+use std::{hash::Hash, net::IpAddr};
 
 #[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
 pub struct Topic {
@@ -17,6 +16,8 @@ pub struct Topic {
     pub author_id: Option<i32>,
     pub author_name: Option<String>,
     pub author_ip: Vec<u8>,
+    /// Set on anonymous boards, where it is shown instead of the author.
+    pub anonymous_name: Option<String>,
     pub is_closed: bool,
     pub is_suspended: bool,
     pub is_hidden: bool,
@@ -30,6 +31,8 @@ pub struct Topic {
 #[table_name = "topics"]
 pub struct TopicForm {
     pub id: i32,
+    pub board_id: Option<i32>,
+    pub title: Option<String>,
     pub is_closed: Option<bool>,
     pub is_suspended: Option<bool>,
     pub is_hidden: Option<bool>,
@@ -50,6 +53,7 @@ pub struct TopicPublic {
     pub title: String,
     pub author_id: Option<i32>,
     pub author_name: String,
+    pub anonymous_name: Option<String>,
     pub is_closed: bool,
     pub is_suspended: bool,
     pub is_hidden: bool,
@@ -77,7 +81,7 @@ impl Topic {
         author_id: Option<i32>,
         author_name: Option<&str>,
         author_ip: &IpAddr,
-    ) -> Result<()> {
+    ) -> Result<Self> {
         let ip_bin: Vec<u8> = match &author_ip {
             IpAddr::V4(ip) => ip.octets().to_vec(),
             IpAddr::V6(ip) => ip.octets().to_vec(),
@@ -92,12 +96,14 @@ impl Topic {
         diesel::insert_into(topics::table)
             .values(new_topic)
             .execute(conn)?;
-        Ok(())
-    }
-
-    pub fn get_latest(conn: &MysqlConnection) -> Result<Self> {
-        let topics = topics::table.order_by(topics::id.desc()).first(conn)?;
-        Ok(topics)
+        let id = last_insert_id(conn)?;
+        if board.is_anonymous {
+            // The pseudonym depends on the id, so it is set once the topic exists
+            diesel::update(topics::table.find(id))
+                .set(topics::anonymous_name.eq(pseudonym(id, author_id, author_ip)))
+                .execute(conn)?;
+        }
+        Self::find_by_id(conn, id)
     }
 
     pub fn get_all(conn: &MysqlConnection, limit: i32, offset: i32) -> Result<Vec<Self>> {
@@ -114,6 +120,50 @@ impl Topic {
         Ok(post)
     }
 
+    /// Searches visible topics by title. `query` is a full-text boolean mode query.
+    pub fn search(
+        conn: &MysqlConnection,
+        query: &str,
+        filter: &SearchFilter,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<Self>> {
+        let mut search = topics::table
+            .filter(
+                sql::<Bool>("MATCH (topics.title) AGAINST (")
+                    .bind::<Text, _>(query.to_owned())
+                    .sql(" IN BOOLEAN MODE)"),
+            )
+            .filter(topics::is_hidden.eq(false))
+            .into_boxed();
+        if let Some(board_id) = filter.board_id {
+            search = search.filter(topics::board_id.eq(board_id));
+        }
+        if let Some(author_id) = filter.author_id {
+            // Anonymous topics must not be found by their authors
+            search = search
+                .filter(topics::author_id.eq(author_id))
+                .filter(topics::anonymous_name.is_null());
+        }
+        if let Some(from) = filter.from {
+            search = search.filter(topics::created_at.ge(from));
+        }
+        if let Some(to) = filter.to {
+            search = search.filter(topics::created_at.lt(to));
+        }
+        let topics = search
+            .order_by(topics::created_at.desc())
+            .limit(limit.into())
+            .offset(offset.into())
+            .load::<Self>(conn)?;
+        Ok(topics)
+    }
+
+    pub fn get_board(&self, conn: &MysqlConnection) -> Result<Board> {
+        let board = boards::table.find(self.board_id).first::<Board>(conn)?;
+        Ok(board)
+    }
+
     pub fn get_comments(
         &self,
         conn: &MysqlConnection,
@@ -129,21 +179,49 @@ impl Topic {
         Ok(comments)
     }
 
+    /// Returns up to `limit` comments on each side of `comment_id`, with the comment itself.
+    pub fn get_comments_around(
+        &self,
+        conn: &MysqlConnection,
+        comment_id: i32,
+        limit: i32,
+    ) -> Result<Vec<Comment>> {
+        let mut comments = comments::table
+            .filter(comments::topic_id.eq(self.id))
+            .filter(comments::id.lt(comment_id))
+            .order_by(comments::id.desc())
+            .limit(limit.into())
+            .load::<Comment>(conn)?;
+        comments.reverse();
+        let after = comments::table
+            .filter(comments::topic_id.eq(self.id))
+            .filter(comments::id.ge(comment_id))
+            .order_by(comments::id.asc())
+            .limit((limit + 1).into())
+            .load::<Comment>(conn)?;
+        comments.extend(after);
+        Ok(comments)
+    }
+
     pub fn has_ipv6(&self) -> bool {
         self.author_ip[4..].iter().any(|x| *x != 0u8)
     }
 
-    pub fn get_public(&self) -> TopicPublic {
+    /// The author of an anonymous topic is only shown with `show_hidden`,
+    /// which is for those who may view hidden topics.
+    pub fn get_public(&self, show_hidden: bool) -> TopicPublic {
+        let is_masked = self.anonymous_name.is_some() && !show_hidden;
         TopicPublic {
             id: self.id,
             board_id: self.board_id,
             title: self.title.clone(),
-            author_id: self.author_id,
-            author_name: if let Some(name) = &self.author_name {
-                name.clone()
-            } else {
-                self.get_ip_string()
+            author_id: if is_masked { None } else { self.author_id },
+            author_name: match (&self.anonymous_name, &self.author_name) {
+                (Some(anonymous_name), _) if is_masked => anonymous_name.clone(),
+                (_, Some(name)) => name.clone(),
+                (_, None) => display_ip(&self.author_ip),
             },
+            anonymous_name: self.anonymous_name.clone(),
             is_closed: self.is_closed,
             is_suspended: self.is_suspended,
             is_hidden: self.is_hidden,
@@ -153,25 +231,13 @@ impl Topic {
             updated_at: DateTime::<Utc>::from_utc(self.updated_at, Utc),
         }
     }
-
-    fn get_ip_string(&self) -> String {
-        let x: &Vec<u8> = &self.author_ip;
-        if x[4..].iter().any(|x| *x != 0u8) {
-            // IPv6
-            let arr: &[u8; 16] = x[..].try_into().unwrap();
-            Ipv6Addr::from(*arr).to_string()
-        } else {
-            // IPv4
-            let arr: &[u8; 4] = x[0..4].try_into().unwrap();
-            Ipv4Addr::from(*arr).to_string()
-        }
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::db::create_connection;
+    use std::convert::TryInto;
 
     #[test]
     fn test_topic() {
@@ -180,7 +246,7 @@ mod tests {
         conn.test_transaction::<_, diesel::result::Error, _>(|| {
             let boards = Board::get_all(&conn).expect("A board must exist");
             let ip = IpAddr::from_str("2001:db8::1").expect("must succeed");
-            Topic::create(
+            let created = Topic::create(
                 &conn,
                 &boards[0],
                 "test title",
@@ -189,8 +255,9 @@ mod tests {
                 &ip,
             )
             .expect("must succeed");
+            assert_eq!("test title", created.title);
             let topics = Topic::get_all(&conn, 1, 0).expect("must succeed");
-            assert_eq!("test title", topics[0].title);
+            assert_eq!(created.id, topics[0].id);
             assert_eq!(Some("test_author".to_owned()), topics[0].author_name);
             assert_eq!(true, topics[0].has_ipv6());
             let arr: &[u8; 16] = topics[0].author_ip[..].try_into().expect("must succeed");
@@ -212,4 +279,101 @@ mod tests {
             Ok(())
         });
     }
+
+    #[test]
+    fn test_anonymous_topic() {
+        use crate::models::BoardForm;
+        use std::str::FromStr;
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = Board::create(&conn, "익명테스트", "test_anon").expect("must succeed");
+            let board = BoardForm {
+                id: board.id,
+                display_name: None,
+                name: None,
+                is_active: None,
+                allow_anonymous_reactions: None,
+                is_anonymous: Some(true),
+            }
+            .save(&conn)
+            .expect("must succeed");
+            let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+            let topic = Topic::create(
+                &conn,
+                &board,
+                "test title",
+                Some(3),
+                Some("test_author"),
+                &ip,
+            )
+            .expect("must succeed");
+            let comment = Comment::create(
+                &conn,
+                &topic,
+                None,
+                "test",
+                Some(3),
+                Some("test_author"),
+                &ip,
+            )
+            .expect("must succeed");
+            let anonymous_name = topic.anonymous_name.clone().expect("must be set");
+            assert_eq!(Some(anonymous_name.clone()), comment.anonymous_name);
+
+            let public = topic.get_public(false);
+            assert_eq!(None, public.author_id);
+            assert_eq!(anonymous_name, public.author_name);
+            let public = comment.get_public(false);
+            assert_eq!(None, public.author_id);
+            assert_eq!(anonymous_name, public.author_name);
+            let public = topic.get_public(true);
+            assert_eq!(Some(3), public.author_id);
+            assert_eq!("test_author", public.author_name);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_create_concurrently() {
+        use std::str::FromStr;
+        use std::thread;
+        let conn = create_connection();
+        let board_id = Board::get_all(&conn).expect("A board must exist")[0].id;
+        let handles = (0..8)
+            .map(|i| {
+                thread::spawn(move || {
+                    let conn = create_connection();
+                    let board = Board::find_by_id(&conn, board_id).expect("must succeed");
+                    let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+                    let title = format!("concurrent test title {}", i);
+                    conn.transaction::<_, anyhow::Error, _>(|| {
+                        let topic = Topic::create(&conn, &board, &title, None, None, &ip)?;
+                        let comment =
+                            Comment::create(&conn, &topic, None, &title, None, None, &ip)?;
+                        Ok((title.clone(), topic, comment))
+                    })
+                    .expect("must succeed")
+                })
+            })
+            .collect::<Vec<_>>();
+        let results = handles
+            .into_iter()
+            .map(|x| x.join().expect("must succeed"))
+            .collect::<Vec<_>>();
+
+        for (title, topic, comment) in &results {
+            assert_eq!(*title, topic.title);
+            assert_eq!(topic.id, comment.topic_id);
+            assert_eq!(*title, comment.content);
+        }
+
+        // The threads commit, so remove what they created
+        let topic_ids = results.iter().map(|x| x.1.id).collect::<Vec<i32>>();
+        diesel::delete(comments::table.filter(comments::topic_id.eq_any(&topic_ids)))
+            .execute(&conn)
+            .expect("must succeed");
+        diesel::delete(topics::table.filter(topics::id.eq_any(&topic_ids)))
+            .execute(&conn)
+            .expect("must succeed");
+    }
 }
@@ -0,0 +1,69 @@
+use crate::models::Topic;
+use crate::schema::{topic_watches, topics};
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+/// A user watching a topic is notified of new comments on it.
+#[derive(Serialize, Deserialize, Queryable, Identifiable, Debug)]
+#[table_name = "topic_watches"]
+pub struct TopicWatch {
+    pub id: i32,
+    pub topic_id: i32,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "topic_watches"]
+struct NewTopicWatch {
+    pub topic_id: i32,
+    pub user_id: i32,
+}
+
+impl TopicWatch {
+    /// Starts watching. Watching a topic twice does nothing.
+    pub fn add(conn: &MysqlConnection, topic_id: i32, user_id: i32) -> Result<()> {
+        diesel::insert_or_ignore_into(topic_watches::table)
+            .values(NewTopicWatch { topic_id, user_id })
+            .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn remove(conn: &MysqlConnection, topic_id: i32, user_id: i32) -> Result<()> {
+        diesel::delete(
+            topic_watches::table
+                .filter(topic_watches::topic_id.eq(topic_id))
+                .filter(topic_watches::user_id.eq(user_id)),
+        )
+        .execute(conn)?;
+        Ok(())
+    }
+
+    pub fn get_watchers(conn: &MysqlConnection, topic_id: i32) -> Result<Vec<i32>> {
+        let user_ids = topic_watches::table
+            .filter(topic_watches::topic_id.eq(topic_id))
+            .select(topic_watches::user_id)
+            .load::<i32>(conn)?;
+        Ok(user_ids)
+    }
+
+    /// Returns the visible topics watched by a user, most recently updated first.
+    pub fn get_topics(
+        conn: &MysqlConnection,
+        user_id: i32,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<Topic>> {
+        let topics = topic_watches::table
+            .inner_join(topics::table)
+            .filter(topic_watches::user_id.eq(user_id))
+            .filter(topics::is_hidden.eq(false))
+            .select(topics::all_columns)
+            .order_by(topics::updated_at.desc())
+            .limit(limit.into())
+            .offset(offset.into())
+            .load::<Topic>(conn)?;
+        Ok(topics)
+    }
+}
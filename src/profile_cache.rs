@@ -0,0 +1,198 @@
+use crate::auth::Profile;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    pub static ref PROFILE_CACHE: ProfileCache = ProfileCache::new(
+        Duration::from_secs(
+            env::var("PROFILE_CACHE_TTL")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(300)
+        ),
+        Duration::from_secs(
+            env::var("PROFILE_CACHE_STALE_TTL")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(3600)
+        ),
+    );
+}
+
+struct Entry {
+    profile: Profile,
+    fetched_at: Instant,
+    token_expires_at: DateTime<Utc>,
+    refreshing: bool,
+}
+
+pub enum Lookup {
+    /// The entry is younger than the TTL.
+    Fresh(Profile),
+    /// The entry is past the TTL but still usable while it is refreshed.
+    Stale(Profile),
+    Missing,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProfileCacheStats {
+    pub entries: usize,
+    pub hits: u64,
+    pub stale_hits: u64,
+    pub misses: u64,
+}
+
+/// Caches MediaWiki profiles by user id so that the wiki is not called on every request.
+pub struct ProfileCache {
+    entries: RwLock<HashMap<i32, Entry>>,
+    ttl: Duration,
+    stale_ttl: Duration,
+    hits: AtomicU64,
+    stale_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ProfileCache {
+    pub fn new(ttl: Duration, stale_ttl: Duration) -> Self {
+        ProfileCache {
+            entries: RwLock::new(HashMap::new()),
+            ttl,
+            stale_ttl,
+            hits: AtomicU64::new(0),
+            stale_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn lookup(&self, user_id: i32) -> Lookup {
+        let entries = self.entries.read().unwrap();
+        let lookup = match entries.get(&user_id) {
+            Some(entry) if entry.token_expires_at > Utc::now() => {
+                let age = entry.fetched_at.elapsed();
+                if age < self.ttl {
+                    Lookup::Fresh(entry.profile.clone())
+                } else if age < self.ttl + self.stale_ttl {
+                    Lookup::Stale(entry.profile.clone())
+                } else {
+                    Lookup::Missing
+                }
+            }
+            _ => Lookup::Missing,
+        };
+        match lookup {
+            Lookup::Fresh(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            Lookup::Stale(_) => self.stale_hits.fetch_add(1, Ordering::Relaxed),
+            Lookup::Missing => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        lookup
+    }
+
+    pub fn insert(&self, profile: Profile, token_expires_at: DateTime<Utc>) {
+        let mut entries = self.entries.write().unwrap();
+        entries.insert(
+            profile.id,
+            Entry {
+                profile,
+                fetched_at: Instant::now(),
+                token_expires_at,
+                refreshing: false,
+            },
+        );
+    }
+
+    /// Marks a stale entry as being refreshed.
+    /// Returns false if another request is already refreshing it.
+    pub fn begin_refresh(&self, user_id: i32) -> bool {
+        let mut entries = self.entries.write().unwrap();
+        match entries.get_mut(&user_id) {
+            Some(entry) if !entry.refreshing => {
+                entry.refreshing = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn cancel_refresh(&self, user_id: i32) {
+        let mut entries = self.entries.write().unwrap();
+        if let Some(entry) = entries.get_mut(&user_id) {
+            entry.refreshing = false;
+        }
+    }
+
+    pub fn invalidate(&self, user_id: i32) {
+        let mut entries = self.entries.write().unwrap();
+        entries.remove(&user_id);
+    }
+
+    pub fn stats(&self) -> ProfileCacheStats {
+        ProfileCacheStats {
+            entries: self.entries.read().unwrap().len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            stale_hits: self.stale_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_profile(id: i32) -> Profile {
+        Profile {
+            id,
+            username: "test_user".to_owned(),
+            confirmed_email: true,
+            blocked: false,
+            groups: vec![],
+            rights: vec![],
+            email: "test@example.com".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+        let cache = ProfileCache::new(Duration::from_secs(60), Duration::from_secs(60));
+        assert!(matches!(cache.lookup(3), Lookup::Missing));
+        cache.insert(test_profile(3), expires_at);
+        assert!(matches!(cache.lookup(3), Lookup::Fresh(_)));
+        cache.invalidate(3);
+        assert!(matches!(cache.lookup(3), Lookup::Missing));
+
+        let stats = cache.stats();
+        assert_eq!(0, stats.entries);
+        assert_eq!(1, stats.hits);
+        assert_eq!(2, stats.misses);
+    }
+
+    #[test]
+    fn test_stale() {
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+        let cache = ProfileCache::new(Duration::from_secs(0), Duration::from_secs(60));
+        cache.insert(test_profile(3), expires_at);
+        assert!(matches!(cache.lookup(3), Lookup::Stale(_)));
+        assert_eq!(true, cache.begin_refresh(3));
+        assert_eq!(false, cache.begin_refresh(3));
+        cache.insert(test_profile(3), expires_at);
+        assert_eq!(true, cache.begin_refresh(3));
+
+        let cache = ProfileCache::new(Duration::from_secs(0), Duration::from_secs(0));
+        cache.insert(test_profile(3), expires_at);
+        assert!(matches!(cache.lookup(3), Lookup::Missing));
+    }
+
+    #[test]
+    fn test_token_expired() {
+        let expires_at = Utc::now() - chrono::Duration::hours(1);
+        let cache = ProfileCache::new(Duration::from_secs(60), Duration::from_secs(60));
+        cache.insert(test_profile(3), expires_at);
+        assert!(matches!(cache.lookup(3), Lookup::Missing));
+    }
+}
@@ -0,0 +1,419 @@
+use crate::custom_error::{CustomError, ErrorCode};
+use actix_web::http::header::HeaderValue;
+use actix_web::{HttpResponse, ResponseError};
+use lazy_static::lazy_static;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    pub static ref RATE_LIMITER: RateLimiter = RateLimiter::new(Limits {
+        topic_user: Limit::from_env("RATE_LIMIT_TOPIC_USER", Limit::new(5, 600)),
+        topic_anon: Limit::from_env("RATE_LIMIT_TOPIC_ANON", Limit::new(2, 600)),
+        comment_user: Limit::from_env("RATE_LIMIT_COMMENT_USER", Limit::new(10, 60)),
+        comment_anon: Limit::from_env("RATE_LIMIT_COMMENT_ANON", Limit::new(3, 60)),
+        report_user: Limit::from_env("RATE_LIMIT_REPORT_USER", Limit::new(10, 600)),
+        report_anon: Limit::from_env("RATE_LIMIT_REPORT_ANON", Limit::new(3, 600)),
+    });
+}
+
+/// Buckets that are full again are dropped this often.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// Beyond this many buckets, the least recently used tenth of them is dropped.
+const MAX_BUCKETS: usize = 100000;
+
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Action {
+    PostTopic,
+    PostComment,
+    Report,
+}
+
+/// Logged-in users are limited by their id and by their IP, so that several accounts
+/// on one IP share a budget. Anonymous posters are limited by their IP.
+/// IPv6 addresses are limited by their /64 prefix, as a single host usually has all of it.
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum RateLimitKey {
+    User(i32),
+    /// Logged-in users posting from the IP.
+    UserIp(IpAddr),
+    Ip(IpAddr),
+}
+
+/// A bucket holding up to `capacity` tokens, refilled completely over `period`.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+impl Limit {
+    pub fn new(capacity: u32, period_secs: u64) -> Self {
+        Limit {
+            capacity,
+            period: Duration::from_secs(period_secs),
+        }
+    }
+
+    /// Reads a limit written as `capacity/seconds`, e.g. `10/60`.
+    fn from_env(key: &str, default: Limit) -> Self {
+        let value = match env::var(key) {
+            Ok(value) => value,
+            Err(_) => return default,
+        };
+        let mut split = value.splitn(2, '/');
+        match (
+            split.next().and_then(|x| x.trim().parse().ok()),
+            split.next().and_then(|x| x.trim().parse().ok()),
+        ) {
+            (Some(capacity), Some(period_secs)) => Limit::new(capacity, period_secs),
+            _ => panic!("{} is invalid", key),
+        }
+    }
+
+    fn seconds_per_token(&self) -> f64 {
+        self.period.as_secs_f64() / f64::from(self.capacity.max(1))
+    }
+}
+
+pub struct Limits {
+    pub topic_user: Limit,
+    pub topic_anon: Limit,
+    pub comment_user: Limit,
+    pub comment_anon: Limit,
+    pub report_user: Limit,
+    pub report_anon: Limit,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Time until the next token is available.
+    pub retry_after: Duration,
+    /// Time until the bucket is full again.
+    pub reset_after: Duration,
+}
+
+impl RateLimitStatus {
+    pub fn throttled_response(&self) -> HttpResponse {
+        let retry_after = self.retry_after.as_secs().max(1);
+        let mut response = CustomError::from(ErrorCode::RateLimited)
+            .with_details(serde_json::json!({ "retry_after": retry_after }))
+            .error_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            "retry-after".parse().unwrap(),
+            HeaderValue::from(retry_after),
+        );
+        headers.insert(
+            "x-ratelimit-limit".parse().unwrap(),
+            HeaderValue::from(self.limit),
+        );
+        headers.insert(
+            "x-ratelimit-remaining".parse().unwrap(),
+            HeaderValue::from(self.remaining),
+        );
+        headers.insert(
+            "x-ratelimit-reset".parse().unwrap(),
+            HeaderValue::from(self.reset_after.as_secs()),
+        );
+        response
+    }
+}
+
+struct Buckets {
+    map: HashMap<(Action, RateLimitKey), Bucket>,
+    swept_at: Instant,
+}
+
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+    limits: Limits,
+}
+
+impl RateLimiter {
+    pub fn new(limits: Limits) -> Self {
+        RateLimiter {
+            buckets: Mutex::new(Buckets {
+                map: HashMap::new(),
+                swept_at: Instant::now(),
+            }),
+            limits,
+        }
+    }
+
+    fn get_limit(&self, action: Action, key: &RateLimitKey) -> Limit {
+        match (action, key) {
+            (Action::PostTopic, RateLimitKey::User(_) | RateLimitKey::UserIp(_)) => {
+                self.limits.topic_user
+            }
+            (Action::PostTopic, RateLimitKey::Ip(_)) => self.limits.topic_anon,
+            (Action::PostComment, RateLimitKey::User(_) | RateLimitKey::UserIp(_)) => {
+                self.limits.comment_user
+            }
+            (Action::PostComment, RateLimitKey::Ip(_)) => self.limits.comment_anon,
+            (Action::Report, RateLimitKey::User(_) | RateLimitKey::UserIp(_)) => {
+                self.limits.report_user
+            }
+            (Action::Report, RateLimitKey::Ip(_)) => self.limits.report_anon,
+        }
+    }
+
+    /// Takes a token from the buckets of a request by `user_id`, or by an anonymous user
+    /// if `None`, if every one of them has one available.
+    pub fn check(&self, action: Action, user_id: Option<i32>, ip: IpAddr) -> RateLimitStatus {
+        self.check_at(action, &get_keys(user_id, ip), Instant::now())
+    }
+
+    /// Returns the status of the bucket that throttles the request, or that will throttle it first.
+    fn check_at(&self, action: Action, keys: &[RateLimitKey], now: Instant) -> RateLimitStatus {
+        let mut guard = self.buckets.lock().unwrap();
+        let Buckets {
+            map: buckets,
+            swept_at,
+        } = &mut *guard;
+        if now.saturating_duration_since(*swept_at) >= SWEEP_INTERVAL {
+            buckets.retain(|(action, key), bucket| {
+                let limit = self.get_limit(*action, key);
+                let elapsed = now.saturating_duration_since(bucket.updated_at);
+                bucket.tokens + elapsed.as_secs_f64() / limit.seconds_per_token()
+                    < f64::from(limit.capacity)
+            });
+            *swept_at = now;
+        }
+        if buckets.len() >= MAX_BUCKETS {
+            let mut oldest = buckets
+                .iter()
+                .map(|(key, bucket)| (bucket.updated_at, *key))
+                .collect::<Vec<(Instant, (Action, RateLimitKey))>>();
+            oldest.select_nth_unstable_by_key(MAX_BUCKETS / 10, |x| x.0);
+            for (_, key) in &oldest[..MAX_BUCKETS / 10] {
+                buckets.remove(key);
+            }
+        }
+        // Every bucket is refilled before any token is taken,
+        // so that a throttled request does not use up the other buckets
+        for key in keys {
+            let limit = self.get_limit(action, key);
+            let capacity = f64::from(limit.capacity);
+            let bucket = buckets.entry((action, *key)).or_insert(Bucket {
+                tokens: capacity,
+                updated_at: now,
+            });
+            let elapsed = now.saturating_duration_since(bucket.updated_at);
+            bucket.tokens =
+                (bucket.tokens + elapsed.as_secs_f64() / limit.seconds_per_token()).min(capacity);
+            bucket.updated_at = now;
+        }
+        let allowed = keys
+            .iter()
+            .all(|key| buckets[&(action, *key)].tokens >= 1.0);
+
+        keys.iter()
+            .map(|key| {
+                let limit = self.get_limit(action, key);
+                let capacity = f64::from(limit.capacity);
+                let seconds_per_token = limit.seconds_per_token();
+                let bucket = buckets.get_mut(&(action, *key)).unwrap();
+                if allowed {
+                    bucket.tokens -= 1.0;
+                }
+                let retry_after = if bucket.tokens >= 1.0 {
+                    Duration::from_secs(0)
+                } else {
+                    Duration::from_secs_f64((1.0 - bucket.tokens) * seconds_per_token)
+                };
+                RateLimitStatus {
+                    allowed,
+                    limit: limit.capacity,
+                    remaining: bucket.tokens.floor() as u32,
+                    retry_after,
+                    reset_after: Duration::from_secs_f64(
+                        (capacity - bucket.tokens) * seconds_per_token,
+                    ),
+                }
+            })
+            .max_by_key(|x| (x.retry_after, Reverse(x.remaining)))
+            .expect("a request has at least one bucket")
+    }
+}
+
+fn get_keys(user_id: Option<i32>, ip: IpAddr) -> Vec<RateLimitKey> {
+    let ip = match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(ip) => {
+            let s = ip.segments();
+            Ipv6Addr::new(s[0], s[1], s[2], s[3], 0, 0, 0, 0).into()
+        }
+    };
+    match user_id {
+        Some(user_id) => vec![RateLimitKey::User(user_id), RateLimitKey::UserIp(ip)],
+        None => vec![RateLimitKey::Ip(ip)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn test_limiter() -> RateLimiter {
+        RateLimiter::new(Limits {
+            topic_user: Limit::new(2, 60),
+            topic_anon: Limit::new(1, 60),
+            comment_user: Limit::new(10, 60),
+            comment_anon: Limit::new(3, 60),
+            report_user: Limit::new(10, 600),
+            report_anon: Limit::new(3, 600),
+        })
+    }
+
+    #[test]
+    fn test_check() {
+        let limiter = test_limiter();
+        let now = Instant::now();
+        let key = [RateLimitKey::User(3)];
+        let status = limiter.check_at(Action::PostTopic, &key, now);
+        assert_eq!(true, status.allowed);
+        assert_eq!(1, status.remaining);
+        let status = limiter.check_at(Action::PostTopic, &key, now);
+        assert_eq!(true, status.allowed);
+        assert_eq!(0, status.remaining);
+        let status = limiter.check_at(Action::PostTopic, &key, now);
+        assert_eq!(false, status.allowed);
+        assert_eq!(2, status.limit);
+        assert_eq!(30, status.retry_after.as_secs());
+
+        // Comments have their own budget
+        let status = limiter.check_at(Action::PostComment, &key, now);
+        assert_eq!(true, status.allowed);
+
+        // One token is refilled every 30 seconds
+        let status = limiter.check_at(Action::PostTopic, &key, now + Duration::from_secs(30));
+        assert_eq!(true, status.allowed);
+    }
+
+    #[test]
+    fn test_anonymous() {
+        let limiter = test_limiter();
+        let now = Instant::now();
+        let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+        let status = limiter.check_at(Action::PostTopic, &[RateLimitKey::Ip(ip)], now);
+        assert_eq!(true, status.allowed);
+        let status = limiter.check_at(Action::PostTopic, &[RateLimitKey::Ip(ip)], now);
+        assert_eq!(false, status.allowed);
+        assert_eq!(1, status.limit);
+
+        let other = IpAddr::from_str("127.0.0.4").expect("must succeed");
+        let status = limiter.check_at(Action::PostTopic, &[RateLimitKey::Ip(other)], now);
+        assert_eq!(true, status.allowed);
+    }
+
+    #[test]
+    fn test_accounts_on_one_ip() {
+        let limiter = test_limiter();
+        let now = Instant::now();
+        let ip = IpAddr::from_str("127.0.0.3").expect("must succeed");
+        let first = [RateLimitKey::User(3), RateLimitKey::UserIp(ip)];
+        let second = [RateLimitKey::User(4), RateLimitKey::UserIp(ip)];
+        let status = limiter.check_at(Action::PostTopic, &first, now);
+        assert_eq!(true, status.allowed);
+        assert_eq!(1, status.remaining);
+        let status = limiter.check_at(Action::PostTopic, &second, now);
+        assert_eq!(true, status.allowed);
+        assert_eq!(0, status.remaining);
+        let status = limiter.check_at(Action::PostTopic, &second, now);
+        assert_eq!(false, status.allowed);
+        assert_eq!(30, status.retry_after.as_secs());
+
+        // The throttled request took no token from the user
+        let other = IpAddr::from_str("127.0.0.4").expect("must succeed");
+        let status = limiter.check_at(
+            Action::PostTopic,
+            &[RateLimitKey::User(4), RateLimitKey::UserIp(other)],
+            now,
+        );
+        assert_eq!(true, status.allowed);
+        assert_eq!(0, status.remaining);
+    }
+
+    #[test]
+    fn test_ipv6_prefix() {
+        let limiter = test_limiter();
+        let now = Instant::now();
+        let ip = IpAddr::from_str("2001:db8:1:2::3").expect("must succeed");
+        let status = limiter.check_at(Action::PostTopic, &get_keys(None, ip), now);
+        assert_eq!(true, status.allowed);
+
+        // Another address in the same /64 shares the bucket
+        let same = IpAddr::from_str("2001:db8:1:2:ffff::4").expect("must succeed");
+        let status = limiter.check_at(Action::PostTopic, &get_keys(None, same), now);
+        assert_eq!(false, status.allowed);
+        let status = limiter.check_at(Action::PostTopic, &get_keys(Some(3), same), now);
+        assert_eq!(true, status.allowed);
+        let status = limiter.check_at(Action::PostTopic, &get_keys(Some(4), ip), now);
+        assert_eq!(true, status.allowed);
+        let status = limiter.check_at(Action::PostTopic, &get_keys(Some(5), ip), now);
+        assert_eq!(false, status.allowed);
+
+        let other = IpAddr::from_str("2001:db8:1:3::3").expect("must succeed");
+        let status = limiter.check_at(Action::PostTopic, &get_keys(None, other), now);
+        assert_eq!(true, status.allowed);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let limiter = test_limiter();
+        let now = Instant::now();
+        let key = [RateLimitKey::User(3)];
+        limiter.check_at(Action::PostTopic, &key, now);
+        limiter.check_at(Action::Report, &key, now);
+        limiter.check_at(Action::Report, &key, now);
+
+        // Only buckets that are full again are dropped
+        let later = now + SWEEP_INTERVAL;
+        limiter.check_at(Action::PostComment, &key, later);
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(2, buckets.map.len());
+        assert_eq!(
+            false,
+            buckets.map.contains_key(&(Action::PostTopic, key[0]))
+        );
+    }
+
+    #[test]
+    fn test_max_buckets() {
+        let limiter = test_limiter();
+        let now = Instant::now();
+        for i in 0..MAX_BUCKETS {
+            let later = now + Duration::from_micros(i as u64);
+            limiter.check_at(Action::Report, &[RateLimitKey::User(i as i32)], later);
+        }
+        let later = now + Duration::from_micros(MAX_BUCKETS as u64);
+        limiter.check_at(Action::Report, &[RateLimitKey::User(-1)], later);
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(MAX_BUCKETS - MAX_BUCKETS / 10 + 1, buckets.map.len());
+        assert_eq!(
+            false,
+            buckets
+                .map
+                .contains_key(&(Action::Report, RateLimitKey::User(0)))
+        );
+        assert_eq!(
+            true,
+            buckets
+                .map
+                .contains_key(&(Action::Report, RateLimitKey::User(-1)))
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::db::DbPool;
+use crate::models::{Log, LogContent, LogType};
+use chrono::{Duration as ChronoDuration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_types::Timestamp;
+use lazy_static::lazy_static;
+use std::env;
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+lazy_static! {
+    /// Stored IPs are truncated after this many days. Zero or less turns purging off.
+    pub static ref IP_RETENTION_DAYS: i64 = match env::var("IP_RETENTION_DAYS") {
+        Ok(x) => x.parse().expect("IP_RETENTION_DAYS is invalid"),
+        Err(_) => 90,
+    };
+}
+
+/// Purges run this often, starting when the server starts.
+const PURGE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Tables with an IP column, whether they have an `updated_at` to preserve,
+/// and a condition on the rows to purge.
+/// Timestamps must not change, as topics are sorted by them.
+/// Open reports keep their IPs, which tell anonymous reporters apart.
+const IP_COLUMNS: [(&str, &str, bool, &str); 5] = [
+    ("topics", "author_ip", true, ""),
+    ("comments", "author_ip", true, ""),
+    ("comment_revisions", "user_ip", false, ""),
+    ("logs", "user_ip", false, ""),
+    ("reports", "user_ip", false, " AND status <> 'open'"),
+];
+
+/// Keeps the first 2 bytes of an IPv4 address and the first 8 of an IPv6 one,
+/// the same prefixes as the masked display, so that ranges can still be banned.
+fn truncated(column: &str) -> String {
+    format!(
+        "IF(LENGTH({0}) = 16, CONCAT(LEFT({0}, 8), UNHEX('0000000000000000')), CONCAT(LEFT({0}, 2), UNHEX('0000')))",
+        column
+    )
+}
+
+/// Truncates IPs stored before `cutoff`, returning the number of rows changed in each table.
+///
+/// Anonymous reactions are unique by IP, so reactions of the same emoji from one range
+/// would collide once truncated. All but one of them are deleted.
+pub fn purge_ips(
+    conn: &MysqlConnection,
+    cutoff: NaiveDateTime,
+) -> anyhow::Result<Vec<(&'static str, usize)>> {
+    let mut counts = Vec::new();
+    for (table, column, has_updated_at, condition) in IP_COLUMNS.iter() {
+        let query = format!(
+            "UPDATE {table} SET {column} = {truncated}{keep} WHERE created_at < ? AND {column} <> {truncated}{condition}",
+            table = table,
+            column = column,
+            truncated = truncated(column),
+            condition = condition,
+            keep = if *has_updated_at {
+                ", updated_at = updated_at"
+            } else {
+                ""
+            },
+        );
+        let count = diesel::sql_query(query)
+            .bind::<Timestamp, _>(cutoff)
+            .execute(conn)?;
+        counts.push((*table, count));
+    }
+
+    let query = format!(
+        "UPDATE IGNORE comment_reactions SET user_ip = {truncated} WHERE created_at < ? AND user_ip <> {truncated}",
+        truncated = truncated("user_ip"),
+    );
+    let count = diesel::sql_query(query)
+        .bind::<Timestamp, _>(cutoff)
+        .execute(conn)?;
+    counts.push(("comment_reactions", count));
+    let query = format!(
+        "DELETE FROM comment_reactions WHERE created_at < ? AND user_ip <> {truncated}",
+        truncated = truncated("user_ip"),
+    );
+    let count = diesel::sql_query(query)
+        .bind::<Timestamp, _>(cutoff)
+        .execute(conn)?;
+    counts.push(("comment_reactions_deleted", count));
+    Ok(counts)
+}
+
+/// Purges IPs older than the retention period and records the run in the logs.
+fn run_purge(conn: &MysqlConnection) -> anyhow::Result<()> {
+    let cutoff = Utc::now().naive_utc() - ChronoDuration::days(*IP_RETENTION_DAYS);
+    conn.transaction::<_, anyhow::Error, _>(|| {
+        let counts = purge_ips(conn, cutoff)?;
+        let mut after = serde_json::Map::new();
+        for (table, count) in counts {
+            after.insert(table.to_owned(), count.into());
+        }
+        Log::add(
+            conn,
+            &LogType::PurgeIps,
+            &LogContent {
+                target: 0,
+                before: Some(cutoff.to_string().into()),
+                after: Some(after.into()),
+            },
+            None,
+            None,
+            &IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        )?;
+        Ok(())
+    })
+}
+
+/// Spawns the daily purge on the current arbiter, unless retention is turned off.
+pub fn start_purge(pool: DbPool) {
+    if *IP_RETENTION_DAYS <= 0 {
+        return;
+    }
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let pool = pool.clone();
+            // A failed run is retried on the next tick
+            let _ = actix_web::web::block(move || {
+                let conn = pool.get()?;
+                run_purge(&conn)
+            })
+            .await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_connection;
+    use crate::models::{Board, Comment, CommentReaction, Topic, REACTION_EMOJIS};
+    use crate::schema::comment_reactions;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_purge_ips() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = &Board::get_all(&conn).expect("A board must exist")[0];
+            let ip = IpAddr::from_str("192.0.2.1").expect("must succeed");
+            let topic =
+                Topic::create(&conn, board, "test title", None, None, &ip).expect("must succeed");
+            let comment = Comment::create(&conn, &topic, None, "test", None, None, &ip)
+                .expect("must succeed");
+
+            // Nothing is old enough yet
+            let past = topic.created_at - ChronoDuration::days(1);
+            purge_ips(&conn, past).expect("must succeed");
+            let kept = Topic::find_by_id(&conn, topic.id).expect("must succeed");
+            assert_eq!(vec![192, 0, 2, 1], kept.author_ip);
+
+            let future = topic.created_at + ChronoDuration::days(1);
+            purge_ips(&conn, future).expect("must succeed");
+            let purged = Topic::find_by_id(&conn, topic.id).expect("must succeed");
+            assert_eq!(vec![192, 0, 0, 0], purged.author_ip);
+            assert_eq!(topic.updated_at, purged.updated_at);
+            let purged = Comment::find_by_id(&conn, comment.id).expect("must succeed");
+            assert_eq!(vec![192, 0, 0, 0], purged.author_ip);
+
+            // Already truncated IPs are left alone
+            let counts = purge_ips(&conn, future).expect("must succeed");
+            assert_eq!(
+                Some(&("topics", 0)),
+                counts.iter().find(|(table, _)| *table == "topics")
+            );
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn test_purge_reaction_ips() {
+        let conn = create_connection();
+        conn.test_transaction::<_, diesel::result::Error, _>(|| {
+            let board = &Board::get_all(&conn).expect("A board must exist")[0];
+            let ip = IpAddr::from_str("192.0.2.1").expect("must succeed");
+            let topic =
+                Topic::create(&conn, board, "test title", None, None, &ip).expect("must succeed");
+            let comment = Comment::create(&conn, &topic, None, "test", None, None, &ip)
+                .expect("must succeed");
+            let emoji = &REACTION_EMOJIS[0];
+            for (user_id, ip) in [
+                (None, "192.0.2.1"),
+                (None, "192.0.2.2"),
+                (Some(3), "192.0.2.3"),
+            ] {
+                let ip = IpAddr::from_str(ip).expect("must succeed");
+                CommentReaction::add(&conn, comment.id, emoji, user_id, &ip).expect("must succeed");
+            }
+
+            // The anonymous reactions collide once truncated, and one of them is deleted
+            let future = topic.created_at + ChronoDuration::days(1);
+            purge_ips(&conn, future).expect("must succeed");
+            let reactions = comment_reactions::table
+                .filter(comment_reactions::comment_id.eq(comment.id))
+                .load::<CommentReaction>(&conn)?;
+            assert_eq!(2, reactions.len());
+            for reaction in reactions {
+                assert_eq!(vec![192, 0, 0, 0], reaction.user_ip);
+            }
+            Ok(())
+        });
+    }
+}
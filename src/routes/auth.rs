@@ -1,5 +1,6 @@
 use crate::auth::RefreshToken;
-use crate::custom_error::CustomError;
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::wiki::rest_url;
 use actix_web::{
     client::Client,
     http::{Cookie, StatusCode},
@@ -45,7 +46,7 @@ async fn login(
 ) -> Result<HttpResponse, CustomError> {
     let client = Client::default();
     let mut res = client
-        .post("https://librewiki.net/rest.php/oauth2/access_token")
+        .post(rest_url("/oauth2/access_token"))
         .send_form(&OauthAccessTokenCodeRequest {
             grant_type: "authorization_code".to_owned(),
             client_id: env::var("OAUTH_CLIENT_ID").expect("OAUTH_CLIENT_ID must be set"),
@@ -112,7 +113,7 @@ async fn refresh(
 ) -> Result<HttpResponse, CustomError> {
     let client = Client::default();
     let mut res = client
-        .post("https://librewiki.net/rest.php/oauth2/access_token")
+        .post(rest_url("/oauth2/access_token"))
         .send_form(&OauthAccessTokenRefreshRequest {
             grant_type: "refresh_token".to_owned(),
             client_id: env::var("OAUTH_CLIENT_ID").expect("OAUTH_CLIENT_ID must be set"),
@@ -125,7 +126,7 @@ async fn refresh(
     match res.status() {
         StatusCode::OK => {}
         _ => {
-            return Ok(HttpResponse::Unauthorized().finish());
+            return Err(ErrorCode::TokenInvalid.into());
         }
     }
     let data = res
@@ -0,0 +1,193 @@
+use crate::auth::{Profile, UserInfo};
+use crate::connection_info::ConnectionInfo;
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::db::DbPool;
+use crate::models::{Ban, BanPublic, IpRange, Log, LogContent, LogType, Permission, Permissions};
+use actix_web::{
+    delete, get, post, web,
+    web::{block, Data, Path, Query},
+    HttpResponse, Scope,
+};
+use actix_web_validator::Json;
+use chrono::{DateTime, Utc};
+use diesel::Connection;
+use std::str::FromStr;
+use validator::Validate;
+
+#[derive(Deserialize, Debug)]
+struct GetBansQuery {
+    limit: Option<i32>,
+    offset: Option<i32>,
+    include_expired: Option<bool>,
+}
+
+#[get("")]
+async fn get_bans(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    query: Query<GetBansQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let limit = query.limit.unwrap_or(20);
+    let limit = if limit > 100 { 100 } else { limit };
+    let offset = query.offset.unwrap_or(0);
+    let include_expired = query.include_expired.unwrap_or(false);
+
+    let conn = pool.get()?;
+    let bans = block(move || -> Result<_, CustomError> {
+        let permissions = Permissions::get(&conn, &profile)?;
+        if !permissions.has(Permission::ManageBans, None) {
+            return Err(ErrorCode::PermissionDenied.into());
+        }
+        Ok(Ban::get_all(&conn, limit, offset, include_expired)?)
+    })
+    .await?;
+    let bans = bans
+        .iter()
+        .map(|x| x.get_public())
+        .collect::<Vec<BanPublic>>();
+    Ok(HttpResponse::Ok().json(bans))
+}
+
+#[get("{ban_id}")]
+async fn get_ban(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((ban_id,)): Path<(i32,)>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let ban = block(move || -> Result<_, CustomError> {
+        let permissions = Permissions::get(&conn, &profile)?;
+        if !permissions.has(Permission::ManageBans, None) {
+            return Err(ErrorCode::PermissionDenied.into());
+        }
+        Ok(Ban::find_by_id(&conn, ban_id).map_err(|_| ErrorCode::BanNotFound)?)
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(ban.get_public()))
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct PostBanRequest {
+    cidr: Option<String>,
+    user_id: Option<i32>,
+    #[validate(length(min = 1, max = 1000))]
+    reason: String,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[post("")]
+async fn post_ban(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Json(PostBanRequest {
+        cidr,
+        user_id,
+        reason,
+        expires_at,
+    }): Json<PostBanRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    if cidr.is_none() && user_id.is_none() {
+        return Err(ErrorCode::BanTargetMissing.into());
+    }
+    let ip_range = match cidr.map(|x| IpRange::from_str(&x)) {
+        Some(Ok(ip_range)) => Some(ip_range),
+        Some(Err(_)) => return Err(ErrorCode::InvalidCidr.into()),
+        None => None,
+    };
+
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let ban = block(move || {
+        conn.transaction::<Ban, CustomError, _>(|| {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ManageBans, None) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+            let ban = Ban::create(
+                &conn,
+                ip_range.as_ref(),
+                user_id,
+                &reason,
+                expires_at.map(|x| x.naive_utc()),
+                Some(profile.id),
+                Some(&profile.username),
+            )?;
+            Log::add(
+                &conn,
+                &LogType::Ban,
+                &LogContent {
+                    target: ban.id,
+                    ..Default::default()
+                },
+                Some(profile.id),
+                Some(&profile.username),
+                &ip,
+            )?;
+            Ok(ban)
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(ban.get_public()))
+}
+
+#[delete("{ban_id}")]
+async fn delete_ban(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((ban_id,)): Path<(i32,)>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let ban = block(move || {
+        conn.transaction::<Ban, CustomError, _>(|| {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ManageBans, None) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+            let ban = Ban::find_by_id(&conn, ban_id).map_err(|_| ErrorCode::BanNotFound)?;
+            let lifted = ban.lift(&conn)?;
+            Log::add(
+                &conn,
+                &LogType::Unban,
+                &LogContent {
+                    target: ban_id,
+                    ..Default::default()
+                },
+                Some(profile.id),
+                Some(&profile.username),
+                &ip,
+            )?;
+            Ok(lifted)
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(ban.get_public()))
+}
+
+pub fn scope() -> Scope {
+    web::scope("/bans")
+        .service(get_bans)
+        .service(get_ban)
+        .service(post_ban)
+        .service(delete_ban)
+}
@@ -1,18 +1,43 @@
-use crate::custom_error::CustomError;
+use std::net::IpAddr;
+
+use crate::auth::{Profile, UserInfo};
+use crate::connection_info::ConnectionInfo;
+use crate::custom_error::{CustomError, ErrorCode};
 use crate::db::DbPool;
-use crate::models::{Board, BoardPublic, TopicPublic};
-use actix_web::error::BlockingError;
+use crate::events::{stream_response, Channel};
+use crate::models::{
+    Board, BoardForm, BoardPublic, Feed, Log, LogContent, LogType, Permission, Permissions,
+    PublicEntity, TopicOrder, TopicPublic, FEED_SIZE,
+};
 use actix_web::{
-    get, web,
+    get, patch, post, put, web,
     web::{block, Data, Path, Query},
-    HttpResponse, Scope,
+    HttpRequest, HttpResponse, Scope,
 };
-use derive_more::Display;
+use actix_web_validator::Json;
+use diesel::{Connection, MysqlConnection};
+use validator::{Validate, ValidationError};
+
+#[derive(Deserialize, Debug)]
+struct GetBoardsQuery {
+    active_only: Option<bool>,
+}
 
 #[get("")]
-async fn get_boards(pool: Data<DbPool>) -> Result<HttpResponse, CustomError> {
+async fn get_boards(
+    pool: Data<DbPool>,
+    query: Query<GetBoardsQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let active_only = query.active_only.unwrap_or(false);
     let conn = pool.get()?;
-    let boards = block(move || Board::get_all(&conn)).await?;
+    let boards = block(move || {
+        if active_only {
+            Board::get_active(&conn)
+        } else {
+            Board::get_all(&conn)
+        }
+    })
+    .await?;
     let boards = boards
         .iter()
         .map(|x| x.get_public())
@@ -23,6 +48,255 @@ async fn get_boards(pool: Data<DbPool>) -> Result<HttpResponse, CustomError> {
         .json(boards))
 }
 
+fn validate_board_name(name: &str) -> Result<(), ValidationError> {
+    if name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(ValidationError::new("invalid_board_name"))
+    }
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct PostBoardRequest {
+    #[validate(length(min = 1, max = 255))]
+    display_name: String,
+    #[validate(length(min = 1, max = 255), custom = "validate_board_name")]
+    name: String,
+}
+
+#[post("")]
+async fn post_board(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Json(PostBoardRequest { display_name, name }): Json<PostBoardRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let board = block(move || {
+        conn.transaction::<Board, CustomError, _>(|| {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ManageBoards, None) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+            if Board::find_by_name(&conn, &name).is_ok() {
+                return Err(ErrorCode::BoardNameTaken.into());
+            }
+            let board = Board::create(&conn, &display_name, &name)?;
+            Log::add(
+                &conn,
+                &LogType::CreateBoard,
+                &LogContent {
+                    target: board.id,
+                    ..Default::default()
+                },
+                Some(profile.id),
+                Some(&profile.username),
+                &ip,
+            )?;
+            Ok(board)
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(board.get_public()))
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct PatchBoardRequest {
+    #[validate(length(min = 1, max = 255))]
+    display_name: Option<String>,
+    #[validate(length(min = 1, max = 255), custom = "validate_board_name")]
+    name: Option<String>,
+    allow_anonymous_reactions: Option<bool>,
+    is_anonymous: Option<bool>,
+}
+
+#[patch("{board_id}")]
+async fn patch_board(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((board_id,)): Path<(i32,)>,
+    Json(PatchBoardRequest {
+        display_name,
+        name,
+        allow_anonymous_reactions,
+        is_anonymous,
+    }): Json<PatchBoardRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    if display_name.is_none()
+        && name.is_none()
+        && allow_anonymous_reactions.is_none()
+        && is_anonymous.is_none()
+    {
+        return Err(ErrorCode::NothingToChange.into());
+    }
+
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let board = block(move || {
+        conn.transaction::<Board, CustomError, _>(|| {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ManageBoards, None) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+            let board = Board::find_by_id(&conn, board_id).map_err(|_| ErrorCode::BoardNotFound)?;
+            if let Some(name) = &name {
+                if let Ok(other) = Board::find_by_name(&conn, name) {
+                    if other.id != board_id {
+                        return Err(ErrorCode::BoardNameTaken.into());
+                    }
+                }
+            }
+            let is_renamed = display_name.is_some() || name.is_some();
+            let board_changes = BoardForm {
+                id: board_id,
+                display_name,
+                name,
+                is_active: None,
+                allow_anonymous_reactions,
+                is_anonymous,
+            };
+            let changed = board_changes.save(&conn)?;
+            if changed.is_anonymous && !board.is_anonymous {
+                changed.fill_pseudonyms(&conn)?;
+            }
+            if is_renamed {
+                Log::add(
+                    &conn,
+                    &LogType::RenameBoard,
+                    &LogContent {
+                        target: board_id,
+                        ..Default::default()
+                    },
+                    Some(profile.id),
+                    Some(&profile.username),
+                    &ip,
+                )?;
+            }
+            if allow_anonymous_reactions.is_some() || is_anonymous.is_some() {
+                Log::add(
+                    &conn,
+                    &LogType::ConfigureBoard,
+                    &LogContent {
+                        target: board_id,
+                        before: Some(serde_json::json!({
+                            "allow_anonymous_reactions": board.allow_anonymous_reactions,
+                            "is_anonymous": board.is_anonymous,
+                        })),
+                        after: Some(serde_json::json!({
+                            "allow_anonymous_reactions": changed.allow_anonymous_reactions,
+                            "is_anonymous": changed.is_anonymous,
+                        })),
+                    },
+                    Some(profile.id),
+                    Some(&profile.username),
+                    &ip,
+                )?;
+            }
+            Ok(changed)
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(board.get_public()))
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct PutBoardStatusRequest {
+    is_active: Option<bool>,
+}
+
+fn log_put_board_status(
+    conn: &MysqlConnection,
+    board_id: i32,
+    user_id: Option<i32>,
+    user_name: Option<&str>,
+    user_ip: &IpAddr,
+    req_status: PutBoardStatusRequest,
+) -> anyhow::Result<()> {
+    let log_type = match req_status {
+        PutBoardStatusRequest {
+            is_active: Some(false),
+        } => LogType::DeactivateBoard,
+        PutBoardStatusRequest {
+            is_active: Some(true),
+        } => LogType::ReactivateBoard,
+        _ => return Ok(()),
+    };
+    Log::add(
+        conn,
+        &log_type,
+        &LogContent {
+            target: board_id,
+            ..Default::default()
+        },
+        user_id,
+        user_name,
+        user_ip,
+    )?;
+    Ok(())
+}
+
+#[put("{board_id}/status")]
+async fn put_board_status(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((board_id,)): Path<(i32,)>,
+    Json(req_status): Json<PutBoardStatusRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    if req_status.is_active.is_none() {
+        return Err(ErrorCode::NothingToChange.into());
+    }
+
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let board = block(move || {
+        conn.transaction::<Board, CustomError, _>(|| {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ManageBoards, None) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+            Board::find_by_id(&conn, board_id).map_err(|_| ErrorCode::BoardNotFound)?;
+            let board_changes = BoardForm {
+                id: board_id,
+                display_name: None,
+                name: None,
+                is_active: req_status.is_active,
+                allow_anonymous_reactions: None,
+                is_anonymous: None,
+            };
+            let changed = board_changes.save(&conn)?;
+            log_put_board_status(
+                &conn,
+                board_id,
+                Some(profile.id),
+                Some(&profile.username),
+                &ip,
+                req_status,
+            )?;
+            Ok(changed)
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(board.get_public()))
+}
+
 #[derive(Deserialize, Debug)]
 struct GetTopicsQuery {
     limit: Option<i32>,
@@ -35,43 +309,97 @@ async fn get_board_topics(
     Path((board_id,)): Path<(i32,)>,
     query: Query<GetTopicsQuery>,
 ) -> Result<HttpResponse, CustomError> {
-    #[derive(Debug, Display)]
-    enum ErrorKind {
-        BoardNotFound,
-        OtherError(anyhow::Error),
-    }
-
     let limit = query.limit.unwrap_or(10);
     let limit = if limit > 20 { 20 } else { limit };
     let offset = query.offset.unwrap_or(0);
 
     let conn = pool.get()?;
-    let res = block(move || {
-        let board = Board::find_by_id(&conn, board_id).map_err(|_| ErrorKind::BoardNotFound)?;
-        let topics = board
-            .get_topics(&conn, limit, offset, false)
-            .map_err(|e| ErrorKind::OtherError(e))?;
+    let topics = block(move || -> Result<_, CustomError> {
+        let board = Board::find_by_id(&conn, board_id).map_err(|_| ErrorCode::BoardNotFound)?;
+        let topics = board.get_topics(&conn, limit, offset, false, TopicOrder::Bumped)?;
         Ok(topics)
     })
-    .await;
-    match res {
-        Ok(topics) => {
-            let topics = topics
-                .iter()
-                .map(|x| x.get_public())
-                .collect::<Vec<TopicPublic>>();
-            Ok(HttpResponse::Ok().json(topics))
+    .await?;
+    let topics = topics
+        .iter()
+        .map(|x| x.get_public(false))
+        .collect::<Vec<TopicPublic>>();
+    Ok(HttpResponse::Ok().json(topics))
+}
+
+#[get("{board_id}/feed.atom")]
+async fn get_board_feed(
+    pool: Data<DbPool>,
+    Path((board_id,)): Path<(i32,)>,
+    request: HttpRequest,
+) -> Result<HttpResponse, CustomError> {
+    let conn = pool.get()?;
+    let (board, topics) = block(move || -> Result<_, CustomError> {
+        let board = Board::find_by_id(&conn, board_id).map_err(|_| ErrorCode::BoardNotFound)?;
+        let topics = board.get_topics(&conn, FEED_SIZE, 0, false, TopicOrder::Created)?;
+        Ok((board, topics))
+    })
+    .await?;
+    let topics = topics
+        .iter()
+        .map(|x| x.get_public(false))
+        .collect::<Vec<TopicPublic>>();
+    let feed = Feed::from_board(&board.get_public(), &topics);
+    Ok(feed.cache_response_with(
+        &request,
+        "application/atom+xml; charset=utf-8",
+        Feed::to_atom,
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+struct GetEventsQuery {
+    show_hidden: Option<bool>,
+}
+
+#[get("{board_id}/events")]
+async fn get_board_events(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((board_id,)): Path<(i32,)>,
+    query: Query<GetEventsQuery>,
+    request: HttpRequest,
+) -> Result<HttpResponse, CustomError> {
+    let show_hidden = query.show_hidden.unwrap_or(false);
+    let profile = if show_hidden {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
         }
-        Err(BlockingError::Error(ErrorKind::BoardNotFound)) => {
-            Ok(HttpResponse::NotFound().body("Board is not found"))
+    } else {
+        None
+    };
+    let conn = pool.get()?;
+    block(move || -> Result<_, CustomError> {
+        Board::find_by_id(&conn, board_id).map_err(|_| ErrorCode::BoardNotFound)?;
+        if let Some(profile) = profile {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ViewHidden, Some(board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
         }
-        Err(BlockingError::Error(ErrorKind::OtherError(e))) => Err(e.into()),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
-    }
+        Ok(())
+    })
+    .await?;
+    Ok(stream_response(
+        &request,
+        Channel::Board(board_id),
+        show_hidden,
+    ))
 }
 
 pub fn scope() -> Scope {
     web::scope("/boards")
         .service(get_boards)
+        .service(post_board)
+        .service(patch_board)
+        .service(put_board_status)
         .service(get_board_topics)
+        .service(get_board_events)
+        .service(get_board_feed)
 }
@@ -2,17 +2,22 @@ use std::net::IpAddr;
 
 use crate::auth::{Profile, UserInfo};
 use crate::connection_info::ConnectionInfo;
-use crate::custom_error::CustomError;
+use crate::custom_error::{CustomError, ErrorCode};
 use crate::db::DbPool;
-use crate::models::{Comment, CommentForm, Log, LogContent, LogType};
-use actix_web::error::BlockingError;
+use crate::events::{Event, EVENTS};
+use crate::models::{
+    ip_from_bytes, Ban, Comment, CommentForm, CommentPublic, CommentReaction,
+    CommentRevisionPublic, Log, LogContent, LogType, Permission, Permissions, PublicEntity,
+    ReportTarget,
+};
+use crate::rate_limit::{Action, RATE_LIMITER};
+use crate::routes::reports::{file_report, PostReportRequest};
 use actix_web::{
-    get, put, web,
+    delete, get, post, put, web,
     web::{block, Data, Path, Query},
-    HttpResponse, Scope,
+    HttpRequest, HttpResponse, Scope,
 };
 use actix_web_validator::Json;
-use derive_more::Display;
 use diesel::{Connection, MysqlConnection};
 use validator::Validate;
 
@@ -29,21 +34,221 @@ async fn get_comment(
     query: Query<GetCommentQuery>,
 ) -> Result<HttpResponse, CustomError> {
     let show_hidden = query.show_hidden.unwrap_or(false);
-    if show_hidden {
-        let profile = match token {
-            Some(token) => Profile::get(&token).await?,
-            None => return Ok(HttpResponse::Unauthorized().body("TokenMissing")),
-        };
-        if !profile.is_admin() {
-            return Ok(HttpResponse::Forbidden().finish());
+    let profile = if show_hidden {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
         }
-    }
+    } else {
+        None
+    };
+    let conn = pool.get()?;
+    let comment = block(move || -> Result<_, CustomError> {
+        let comment =
+            Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+        if let Some(profile) = profile {
+            let topic = comment.get_topic(&conn)?;
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ViewHidden, Some(topic.board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+        }
+        Ok(Comment::get_public_list(&conn, &[comment], show_hidden)?.remove(0))
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+/// The raw IP of the author, which is otherwise shown according to `IP_DISPLAY`.
+#[get("{comment_id}/author_ip")]
+async fn get_comment_author_ip(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((comment_id,)): Path<(i32,)>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
     let conn = pool.get()?;
-    match block(move || Comment::find_by_id(&conn, comment_id)).await {
-        Ok(comment) => Ok(HttpResponse::Ok().json(comment.get_public(show_hidden))),
-        Err(BlockingError::Error(_)) => Ok(HttpResponse::NotFound().body("Topic is not found")),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
+    let ip = block(move || -> Result<_, CustomError> {
+        let comment =
+            Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+        let topic = comment.get_topic(&conn)?;
+        let permissions = Permissions::get(&conn, &profile)?;
+        if !permissions.has(Permission::ViewIp, Some(topic.board_id)) {
+            return Err(ErrorCode::PermissionDenied.into());
+        }
+        Ok(ip_from_bytes(&comment.author_ip))
+    })
+    .await?;
+    Ok(HttpResponse::Ok()
+        .set_header("Cache-Control", "private, no-cache")
+        .json(serde_json::json!({ "ip": ip.to_string() })))
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct PutCommentRequest {
+    #[validate(length(min = 1, max = 100000))]
+    content: String,
+}
+
+#[put("{comment_id}")]
+async fn put_comment(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((comment_id,)): Path<(i32,)>,
+    Json(PutCommentRequest { content }): Json<PutCommentRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    if profile.blocked {
+        return Err(ErrorCode::UserBlocked.into());
     }
+
+    let conn = pool.get()?;
+    let comment = block(move || {
+        conn.transaction::<CommentPublic, CustomError, _>(|| {
+            let comment =
+                Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+            let topic = comment.get_topic(&conn)?;
+            let permissions = Permissions::get(&conn, &profile)?;
+            // Moderators may edit any comment, authors only their own visible ones
+            if !permissions.has(Permission::EditComment, Some(topic.board_id)) {
+                if comment.author_id != Some(profile.id) {
+                    return Err(ErrorCode::PermissionDenied.into());
+                }
+                if comment.is_hidden {
+                    return Err(ErrorCode::CommentHidden.into());
+                }
+                if topic.is_hidden {
+                    return Err(ErrorCode::TopicHidden.into());
+                } else if topic.is_closed {
+                    return Err(ErrorCode::TopicClosed.into());
+                } else if topic.is_suspended {
+                    return Err(ErrorCode::TopicSuspended.into());
+                }
+                let board = topic.get_board(&conn)?;
+                if !board.is_active {
+                    return Err(ErrorCode::BoardInactive.into());
+                }
+            }
+            let edited = comment.edit(
+                &conn,
+                &content,
+                Some(profile.id),
+                Some(&profile.username),
+                &ip,
+            )?;
+            Ok(Comment::get_public_list(&conn, &[edited], true)?.remove(0))
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+#[get("{comment_id}/revisions")]
+async fn get_comment_revisions(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((comment_id,)): Path<(i32,)>,
+    query: Query<GetCommentQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let show_hidden = query.show_hidden.unwrap_or(false);
+    let profile = if show_hidden {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
+        }
+    } else {
+        None
+    };
+
+    let conn = pool.get()?;
+    let (comment, revisions) = block(move || -> Result<_, CustomError> {
+        let comment =
+            Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+        if let Some(profile) = profile {
+            let topic = comment.get_topic(&conn)?;
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ViewHidden, Some(topic.board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+        }
+        let revisions = comment.get_revisions(&conn)?;
+        Ok((comment, revisions))
+    })
+    .await?;
+    // On anonymous boards, edits by the author are shown under the pseudonym
+    let is_masked = comment.anonymous_name.is_some() && !show_hidden;
+    let revisions = revisions
+        .iter()
+        .map(|x| {
+            let public = x.get_public(show_hidden || !comment.is_hidden);
+            if is_masked && x.user_id == comment.author_id {
+                CommentRevisionPublic {
+                    user_id: None,
+                    user_name: comment.anonymous_name.clone(),
+                    ..public
+                }
+            } else {
+                public
+            }
+        })
+        .collect::<Vec<CommentRevisionPublic>>();
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+#[derive(Deserialize, Debug)]
+struct GetRepliesQuery {
+    limit: Option<i32>,
+    offset: Option<i32>,
+    show_hidden: Option<bool>,
+}
+
+#[get("{comment_id}/replies")]
+async fn get_comment_replies(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((comment_id,)): Path<(i32,)>,
+    query: Query<GetRepliesQuery>,
+    request: HttpRequest,
+) -> Result<HttpResponse, CustomError> {
+    let limit = query.limit.unwrap_or(10);
+    let limit = if limit > 20 { 20 } else { limit };
+    let offset = query.offset.unwrap_or(0);
+    let show_hidden = query.show_hidden.unwrap_or(false);
+    let profile = if show_hidden {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
+        }
+    } else {
+        None
+    };
+
+    let conn = pool.get()?;
+    let replies = block(move || -> Result<_, CustomError> {
+        let comment =
+            Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+        let topic = comment.get_topic(&conn)?;
+        if let Some(profile) = profile {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ViewHidden, Some(topic.board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+        } else if topic.is_hidden {
+            return Err(ErrorCode::TopicHidden.into());
+        }
+        let replies = comment.get_replies(&conn, limit, offset)?;
+        Ok(Comment::get_public_list(&conn, &replies, show_hidden)?)
+    })
+    .await?;
+    Ok(replies.cache_response(&request))
 }
 
 #[derive(Deserialize, Validate, Debug)]
@@ -73,7 +278,10 @@ fn log_put_comment_status(
     Log::add(
         &conn,
         &log_type,
-        &LogContent { target: comment_id },
+        &LogContent {
+            target: comment_id,
+            ..Default::default()
+        },
         user_id,
         user_name,
         &user_ip,
@@ -89,38 +297,27 @@ async fn put_comment_status(
     Json(req_status): Json<PutCommentStatusRequest>,
     ConnectionInfo { ip }: ConnectionInfo,
 ) -> Result<HttpResponse, CustomError> {
-    #[derive(Debug, Display)]
-    enum ErrorKind {
-        CommentNotFound,
-        OtherError(anyhow::Error),
-    }
-    impl From<diesel::result::Error> for ErrorKind {
-        fn from(error: diesel::result::Error) -> Self {
-            ErrorKind::OtherError(error.into())
-        }
-    }
-
     let profile = match token {
         Some(token) => Profile::get(&token).await?,
-        None => return Ok(HttpResponse::Unauthorized().body("TokenMissing")),
+        None => return Err(ErrorCode::TokenMissing.into()),
     };
 
-    if !profile.is_admin() {
-        return Ok(HttpResponse::Forbidden().finish());
-    }
-
     let conn = pool.get()?;
 
-    let res = block(move || {
-        conn.transaction::<Comment, _, _>(|| {
-            Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorKind::CommentNotFound)?;
+    let comment = block(move || -> Result<_, CustomError> {
+        let (topic, changed) = conn.transaction::<_, CustomError, _>(|| {
+            let comment =
+                Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+            let topic = comment.get_topic(&conn)?;
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::HideComment, Some(topic.board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
             let comment_changes = CommentForm {
                 id: comment_id,
                 is_hidden: req_status.is_hidden,
             };
-            let changed = comment_changes
-                .save(&conn)
-                .map_err(|e| ErrorKind::OtherError(e))?;
+            let changed = comment_changes.save(&conn)?;
             log_put_comment_status(
                 &conn,
                 comment_id,
@@ -128,25 +325,154 @@ async fn put_comment_status(
                 Some(&profile.username),
                 &ip,
                 req_status,
-            )
-            .map_err(|e| ErrorKind::OtherError(e))?;
-            Ok(changed)
-        })
+            )?;
+            Ok((topic, changed))
+        })?;
+        EVENTS.publish(Event::comment_status(&topic, &changed));
+        Ok(Comment::get_public_list(&conn, &[changed], true)?.remove(0))
     })
-    .await;
+    .await?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+/// Checks whether a user, or an anonymous user if `user_id` is `None`,
+/// may react to the comment.
+fn check_reaction_target(
+    conn: &MysqlConnection,
+    comment_id: i32,
+    user_id: Option<i32>,
+    user_ip: &IpAddr,
+) -> Result<Comment, CustomError> {
+    let comment = Comment::find_by_id(conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+    if comment.is_hidden {
+        return Err(ErrorCode::CommentHidden.into());
+    }
+    let topic = comment.get_topic(conn)?;
+    if topic.is_hidden {
+        return Err(ErrorCode::TopicHidden.into());
+    } else if topic.is_closed {
+        return Err(ErrorCode::TopicClosed.into());
+    } else if topic.is_suspended {
+        return Err(ErrorCode::TopicSuspended.into());
+    }
+    let board = topic.get_board(conn)?;
+    if !board.is_active {
+        return Err(ErrorCode::BoardInactive.into());
+    }
+    if user_id.is_none() && !board.allow_anonymous_reactions {
+        return Err(ErrorCode::TokenMissing.into());
+    }
+    if Ban::find_active(conn, user_id, user_ip)?.is_some() {
+        return Err(ErrorCode::UserBlocked.into());
+    }
+    Ok(comment)
+}
 
-    match res {
-        Ok(comment) => Ok(HttpResponse::Ok().json(comment.get_public(true))),
-        Err(BlockingError::Error(ErrorKind::CommentNotFound)) => {
-            Ok(HttpResponse::NotFound().body("Comment is not found"))
+#[put("{comment_id}/reactions/{emoji}")]
+async fn put_comment_reaction(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((comment_id, emoji)): Path<(i32, String)>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    if !CommentReaction::is_allowed(&emoji) {
+        return Err(ErrorCode::InvalidReaction.into());
+    }
+    let profile = match token {
+        Some(token) => Some(Profile::get(&token).await?),
+        None => None,
+    };
+    if let Some(profile) = &profile {
+        if profile.blocked {
+            return Err(ErrorCode::UserBlocked.into());
         }
-        Err(BlockingError::Error(ErrorKind::OtherError(e))) => Err(e.into()),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
     }
+    let user_id = profile.map(|x| x.id);
+
+    let conn = pool.get()?;
+    let comment = block(move || -> Result<_, CustomError> {
+        let comment = check_reaction_target(&conn, comment_id, user_id, &ip)?;
+        CommentReaction::add(&conn, comment.id, &emoji, user_id, &ip)?;
+        Ok(Comment::get_public_list(&conn, &[comment], false)?.remove(0))
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+#[delete("{comment_id}/reactions/{emoji}")]
+async fn delete_comment_reaction(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((comment_id, emoji)): Path<(i32, String)>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Some(Profile::get(&token).await?),
+        None => None,
+    };
+    let user_id = profile.map(|x| x.id);
+
+    let conn = pool.get()?;
+    let comment = block(move || -> Result<_, CustomError> {
+        let comment = check_reaction_target(&conn, comment_id, user_id, &ip)?;
+        CommentReaction::remove(&conn, comment.id, &emoji, user_id, &ip)?;
+        Ok(Comment::get_public_list(&conn, &[comment], false)?.remove(0))
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(comment))
+}
+
+#[post("{comment_id}/reports")]
+async fn post_comment_report(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((comment_id,)): Path<(i32,)>,
+    Json(request): Json<PostReportRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Some(Profile::get(&token).await?),
+        None => None,
+    };
+
+    let rate_limit = RATE_LIMITER.check(Action::Report, profile.as_ref().map(|x| x.id), ip);
+    if !rate_limit.allowed {
+        return Ok(rate_limit.throttled_response());
+    }
+
+    let conn = pool.get()?;
+    block(move || -> Result<_, CustomError> {
+        let comment =
+            Comment::find_by_id(&conn, comment_id).map_err(|_| ErrorCode::CommentNotFound)?;
+        if comment.is_hidden {
+            return Err(ErrorCode::CommentHidden.into());
+        }
+        let topic = comment.get_topic(&conn)?;
+        if topic.is_hidden {
+            return Err(ErrorCode::TopicHidden.into());
+        }
+        file_report(
+            &conn,
+            ReportTarget::Comment,
+            comment.id,
+            request,
+            profile.as_ref(),
+            &ip,
+        )
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 pub fn scope() -> Scope {
     web::scope("/comments")
         .service(get_comment)
+        .service(get_comment_author_ip)
+        .service(put_comment)
+        .service(get_comment_revisions)
+        .service(get_comment_replies)
         .service(put_comment_status)
+        .service(put_comment_reaction)
+        .service(delete_comment_reaction)
+        .service(post_comment_report)
 }
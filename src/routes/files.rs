@@ -1,4 +1,4 @@
-use crate::{custom_error::CustomError, s3};
+use crate::{custom_error, custom_error::CustomError, s3};
 use actix_web::{post, web, HttpResponse, Scope};
 use actix_web_validator::Json;
 use anyhow::anyhow;
@@ -31,6 +31,8 @@ async fn post_files(
 }
 
 pub fn scope() -> Scope {
-    let json_cfg = actix_web_validator::JsonConfig::default().limit(1024 * 1024 * 15); // 15 MiB (base64 - about 30% larger than original)
+    let json_cfg = actix_web_validator::JsonConfig::default()
+        .limit(1024 * 1024 * 15) // 15 MiB (base64 - about 30% larger than original)
+        .error_handler(custom_error::json_error_handler);
     web::scope("/files").app_data(json_cfg).service(post_files)
 }
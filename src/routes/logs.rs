@@ -0,0 +1,72 @@
+use crate::auth::{Profile, UserInfo};
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::db::DbPool;
+use crate::models::{Log, LogFilter, LogPublic, Permission, Permissions};
+use actix_web::{
+    get, web,
+    web::{block, Data, Query},
+    HttpResponse, Scope,
+};
+use chrono::{DateTime, Utc};
+
+#[derive(Deserialize, Debug)]
+struct GetLogsQuery {
+    log_type: Option<String>,
+    target: Option<i32>,
+    user_id: Option<i32>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    before: Option<i32>,
+    limit: Option<i32>,
+    show_ip: Option<bool>,
+}
+
+#[get("")]
+async fn get_logs(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    query: Query<GetLogsQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let show_ip = query.show_ip.unwrap_or(false);
+    let profile = if show_ip {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
+        }
+    } else {
+        None
+    };
+
+    let limit = query.limit.unwrap_or(20);
+    let limit = if limit > 100 { 100 } else { limit };
+    let query = query.into_inner();
+    let filter = LogFilter {
+        log_type: query.log_type,
+        target: query.target,
+        user_id: query.user_id,
+        from: query.from.map(|x| x.naive_utc()),
+        to: query.to.map(|x| x.naive_utc()),
+        before: query.before,
+    };
+
+    let conn = pool.get()?;
+    let logs = block(move || -> Result<_, CustomError> {
+        if let Some(profile) = profile {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ViewIp, None) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+        }
+        Ok(Log::get_all(&conn, &filter, limit)?)
+    })
+    .await?;
+    let logs = logs
+        .into_iter()
+        .map(|(log, log_type)| log.get_public(log_type, show_ip))
+        .collect::<Vec<LogPublic>>();
+    Ok(HttpResponse::Ok().json(logs))
+}
+
+pub fn scope() -> Scope {
+    web::scope("/logs").service(get_logs)
+}
@@ -1,6 +1,14 @@
 use crate::auth::{Profile, UserInfo};
-use crate::custom_error::CustomError;
-use actix_web::{get, web, HttpResponse, Scope};
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::db::DbPool;
+use crate::models::{Notification, NotificationPublic, TopicPublic, TopicWatch};
+use actix_web::{
+    get, put, web,
+    web::{block, Data, Query},
+    HttpResponse, Scope,
+};
+use actix_web_validator::Json;
+use validator::Validate;
 
 #[get("")]
 async fn get_me(UserInfo { token, .. }: UserInfo) -> Result<HttpResponse, CustomError> {
@@ -11,10 +19,123 @@ async fn get_me(UserInfo { token, .. }: UserInfo) -> Result<HttpResponse, Custom
             .set_header("Vary", "Cookie")
             .json(profile))
     } else {
-        Ok(HttpResponse::Unauthorized().body("TokenMissing"))
+        Err(ErrorCode::TokenMissing.into())
     }
 }
 
+#[derive(Deserialize, Debug)]
+struct GetNotificationsQuery {
+    unread_only: Option<bool>,
+    /// Only notifications with a smaller id are returned.
+    before: Option<i32>,
+    limit: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+struct NotificationsResponse {
+    unread_count: i64,
+    notifications: Vec<NotificationPublic>,
+}
+
+#[get("/notifications")]
+async fn get_notifications(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    query: Query<GetNotificationsQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+    let limit = query.limit.unwrap_or(20);
+    let limit = if limit > 50 { 50 } else { limit };
+    let unread_only = query.unread_only.unwrap_or(false);
+    let before = query.before;
+
+    let conn = pool.get()?;
+    let response = block(move || -> Result<_, CustomError> {
+        let unread_count = Notification::count_unread(&conn, profile.id)?;
+        let notifications = Notification::get_all(&conn, profile.id, unread_only, before, limit)?
+            .iter()
+            .map(|(notification, topic, comment)| notification.get_public(topic, comment))
+            .collect::<Vec<NotificationPublic>>();
+        Ok(NotificationsResponse {
+            unread_count,
+            notifications,
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok()
+        .set_header("Cache-Control", "private, no-cache")
+        .json(response))
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct PutNotificationsReadRequest {
+    /// Marks every notification as read if missing.
+    ids: Option<Vec<i32>>,
+}
+
+#[derive(Serialize, Debug)]
+struct UnreadCountResponse {
+    unread_count: i64,
+}
+
+#[put("/notifications/read")]
+async fn put_notifications_read(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Json(PutNotificationsReadRequest { ids }): Json<PutNotificationsReadRequest>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let unread_count = block(move || -> Result<_, CustomError> {
+        Notification::mark_read(&conn, profile.id, ids.as_deref())?;
+        Ok(Notification::count_unread(&conn, profile.id)?)
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(UnreadCountResponse { unread_count }))
+}
+
+#[derive(Deserialize, Debug)]
+struct GetWatchesQuery {
+    limit: Option<i32>,
+    offset: Option<i32>,
+}
+
+#[get("/watches")]
+async fn get_watches(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    query: Query<GetWatchesQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+    let limit = query.limit.unwrap_or(10);
+    let limit = if limit > 20 { 20 } else { limit };
+    let offset = query.offset.unwrap_or(0);
+
+    let conn = pool.get()?;
+    let topics = block(move || TopicWatch::get_topics(&conn, profile.id, limit, offset)).await?;
+    let topics = topics
+        .iter()
+        .map(|x| x.get_public(false))
+        .collect::<Vec<TopicPublic>>();
+    Ok(HttpResponse::Ok()
+        .set_header("Cache-Control", "private, no-cache")
+        .json(topics))
+}
+
 pub fn scope() -> Scope {
-    web::scope("/me").service(get_me)
+    web::scope("/me")
+        .service(get_me)
+        .service(get_notifications)
+        .service(put_notifications_read)
+        .service(get_watches)
 }
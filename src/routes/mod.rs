@@ -1,8 +1,14 @@
 mod auth;
+mod bans;
 mod boards;
 mod comments;
 mod files;
+mod logs;
 mod me;
+mod moderation;
+mod profiles;
+mod reports;
+mod search;
 mod topics;
 
 use actix_web::{get, web, Error, HttpResponse, Scope};
@@ -21,4 +27,10 @@ pub fn scope() -> Scope {
         .service(boards::scope())
         .service(topics::scope())
         .service(comments::scope())
+        .service(logs::scope())
+        .service(profiles::scope())
+        .service(bans::scope())
+        .service(reports::scope())
+        .service(moderation::scope())
+        .service(search::scope())
 }
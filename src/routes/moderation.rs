@@ -0,0 +1,154 @@
+use crate::auth::{Profile, UserInfo};
+use crate::connection_info::ConnectionInfo;
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::db::DbPool;
+use crate::events::{Event, EVENTS};
+use crate::models::{ContentFilter, IpRange, Log, LogContent, LogType, Permission, Permissions};
+use actix_web::{
+    put, web,
+    web::{block, Data},
+    HttpResponse, Scope,
+};
+use actix_web_validator::Json;
+use chrono::{DateTime, Utc};
+use diesel::Connection;
+use std::str::FromStr;
+use validator::Validate;
+
+#[derive(Deserialize, Validate, Debug)]
+struct PutContentStatusRequest {
+    author_id: Option<i32>,
+    /// An IP range, or a single address.
+    cidr: Option<String>,
+    board_id: Option<i32>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    is_hidden: bool,
+    /// Only counts the topics and comments that would change.
+    dry_run: Option<bool>,
+}
+
+/// Hides or unhides every topic and comment of an author or an IP range at once.
+/// Without `board_id`, a site-wide permission is needed.
+#[put("status")]
+async fn put_content_status(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Json(PutContentStatusRequest {
+        author_id,
+        cidr,
+        board_id,
+        from,
+        to,
+        is_hidden,
+        dry_run,
+    }): Json<PutContentStatusRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    if cidr.is_none() && author_id.is_none() {
+        return Err(ErrorCode::ModerationTargetMissing.into());
+    }
+    let ip_range = match cidr.as_ref().map(|x| IpRange::from_str(x)) {
+        Some(Ok(ip_range)) => Some(ip_range),
+        Some(Err(_)) => return Err(ErrorCode::InvalidCidr.into()),
+        None => None,
+    };
+    let filter = ContentFilter {
+        board_id,
+        author_id,
+        ip_range,
+        from: from.map(|x| x.naive_utc()),
+        to: to.map(|x| x.naive_utc()),
+    };
+    let dry_run = dry_run.unwrap_or(false);
+
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let (topic_count, comment_count, topics, comments) = block(move || {
+        conn.transaction::<_, CustomError, _>(|| {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::HideTopic, board_id)
+                || !permissions.has(Permission::HideComment, board_id)
+            {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+            if dry_run {
+                return Ok((
+                    filter.count_topics(&conn, is_hidden)?,
+                    filter.count_comments(&conn, is_hidden)?,
+                    Vec::new(),
+                    Vec::new(),
+                ));
+            }
+
+            let (topics, comments) = filter.set_hidden(&conn, is_hidden)?;
+            let (topic_log_type, comment_log_type, log_type) = if is_hidden {
+                (LogType::HideTopic, LogType::HideComment, LogType::BulkHide)
+            } else {
+                (
+                    LogType::UnhideTopic,
+                    LogType::UnhideComment,
+                    LogType::BulkUnhide,
+                )
+            };
+            let targets = topics
+                .iter()
+                .map(|x| (&topic_log_type, x.id))
+                .chain(comments.iter().map(|(x, _)| (&comment_log_type, x.id)));
+            for (log_type, target) in targets {
+                Log::add(
+                    &conn,
+                    log_type,
+                    &LogContent {
+                        target,
+                        ..Default::default()
+                    },
+                    Some(profile.id),
+                    Some(&profile.username),
+                    &ip,
+                )?;
+            }
+            Log::add(
+                &conn,
+                &log_type,
+                &LogContent {
+                    target: author_id.unwrap_or(0),
+                    before: None,
+                    after: Some(serde_json::json!({
+                        "author_id": author_id,
+                        "cidr": cidr,
+                        "board_id": board_id,
+                        "from": from,
+                        "to": to,
+                        "topic_count": topics.len(),
+                        "comment_count": comments.len(),
+                    })),
+                },
+                Some(profile.id),
+                Some(&profile.username),
+                &ip,
+            )?;
+            Ok((topics.len() as i64, comments.len() as i64, topics, comments))
+        })
+    })
+    .await?;
+    for topic in &topics {
+        EVENTS.publish(Event::topic_status(topic));
+    }
+    for (comment, topic) in &comments {
+        EVENTS.publish(Event::comment_status(topic, comment));
+    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "dry_run": dry_run,
+        "topic_count": topic_count,
+        "comment_count": comment_count,
+    })))
+}
+
+pub fn scope() -> Scope {
+    web::scope("/moderation").service(put_content_status)
+}
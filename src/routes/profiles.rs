@@ -0,0 +1,49 @@
+use crate::auth::{Profile, UserInfo};
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::db::DbPool;
+use crate::models::{Permission, Permissions};
+use crate::profile_cache::PROFILE_CACHE;
+use actix_web::{
+    delete, get, web,
+    web::{block, Data, Path},
+    HttpResponse, Scope,
+};
+
+async fn check_manage_users(pool: &Data<DbPool>, token: Option<String>) -> Result<(), CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+    let conn = pool.get()?;
+    let permissions = block(move || Permissions::get(&conn, &profile)).await?;
+    if !permissions.has(Permission::ManageUsers, None) {
+        return Err(ErrorCode::PermissionDenied.into());
+    }
+    Ok(())
+}
+
+#[get("cache")]
+async fn get_profile_cache(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+) -> Result<HttpResponse, CustomError> {
+    check_manage_users(&pool, token).await?;
+    Ok(HttpResponse::Ok().json(PROFILE_CACHE.stats()))
+}
+
+#[delete("{user_id}/cache")]
+async fn delete_profile_cache(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((user_id,)): Path<(i32,)>,
+) -> Result<HttpResponse, CustomError> {
+    check_manage_users(&pool, token).await?;
+    PROFILE_CACHE.invalidate(user_id);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn scope() -> Scope {
+    web::scope("/profiles")
+        .service(get_profile_cache)
+        .service(delete_profile_cache)
+}
@@ -0,0 +1,246 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::auth::{Profile, UserInfo};
+use crate::connection_info::ConnectionInfo;
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::db::DbPool;
+use crate::events::{Event, EVENTS};
+use crate::models::{
+    Comment, CommentForm, Log, LogContent, LogType, Permission, Permissions, Report, ReportReason,
+    ReportStatus, ReportTarget, Topic, TopicForm,
+};
+use actix_web::{
+    get, put, web,
+    web::{block, Data, Path, Query},
+    HttpResponse, Scope,
+};
+use actix_web_validator::Json;
+use diesel::{Connection, MysqlConnection};
+use validator::Validate;
+
+#[derive(Deserialize, Validate, Debug)]
+pub(super) struct PostReportRequest {
+    reason: ReportReason,
+    #[validate(length(max = 1000))]
+    content: Option<String>,
+}
+
+/// Files a report on a target the reporter may see.
+pub(super) fn file_report(
+    conn: &MysqlConnection,
+    target_type: ReportTarget,
+    target_id: i32,
+    request: PostReportRequest,
+    profile: Option<&Profile>,
+    ip: &IpAddr,
+) -> Result<(), CustomError> {
+    let created = Report::create(
+        conn,
+        target_type,
+        target_id,
+        request.reason,
+        request.content.as_deref().unwrap_or(""),
+        profile.map(|x| x.id),
+        profile.map(|x| x.username.as_str()),
+        ip,
+    )?;
+    if !created {
+        return Err(ErrorCode::AlreadyReported.into());
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+struct GetReportsQuery {
+    board_id: Option<i32>,
+    limit: Option<u32>,
+}
+
+/// Open reports grouped by target. Without `board_id`, a site-wide permission is needed.
+#[get("")]
+async fn get_reports(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    query: Query<GetReportsQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let limit = query.limit.unwrap_or(20);
+    let limit = if limit > 100 { 100 } else { limit };
+    let board_id = query.board_id;
+
+    let conn = pool.get()?;
+    let groups = block(move || -> Result<_, CustomError> {
+        let permissions = Permissions::get(&conn, &profile)?;
+        if !permissions.has(Permission::ManageReports, board_id) {
+            return Err(ErrorCode::PermissionDenied.into());
+        }
+        Ok(Report::get_open_groups(&conn, board_id, limit)?)
+    })
+    .await?;
+    Ok(HttpResponse::Ok()
+        .set_header("Cache-Control", "private, no-cache")
+        .json(groups))
+}
+
+#[derive(Deserialize, Validate, Debug)]
+struct PutReportStatusRequest {
+    status: ReportStatus,
+    /// Hides the target along with resolving its reports.
+    hide_target: Option<bool>,
+}
+
+/// The target of reports being closed, after it was hidden if requested.
+enum Closed {
+    Topic(Topic),
+    Comment(Topic, Comment),
+}
+
+/// Resolves or dismisses every open report on a target.
+#[put("{target_type}/{target_id}/status")]
+async fn put_report_status(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((target_type, target_id)): Path<(String, i32)>,
+    Json(PutReportStatusRequest {
+        status,
+        hide_target,
+    }): Json<PutReportStatusRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    let target_type =
+        ReportTarget::from_str(&target_type).map_err(|_| ErrorCode::InvalidRequest)?;
+    let hide_target = hide_target.unwrap_or(false);
+    let log_type = match status {
+        ReportStatus::Open => return Err(ErrorCode::InvalidRequest.into()),
+        ReportStatus::Resolved => LogType::ResolveReport,
+        ReportStatus::Dismissed if hide_target => {
+            return Err(CustomError::from(ErrorCode::InvalidRequest)
+                .with_message("Dismissed reports cannot hide the target"))
+        }
+        ReportStatus::Dismissed => LogType::DismissReport,
+    };
+
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let (closed, is_hidden) = block(move || {
+        conn.transaction::<_, CustomError, _>(|| {
+            let permissions = Permissions::get(&conn, &profile)?;
+            let (closed, was_hidden) = match target_type {
+                ReportTarget::Topic => {
+                    let topic = Topic::find_by_id(&conn, target_id)
+                        .map_err(|_| ErrorCode::TopicNotFound)?;
+                    let was_hidden = topic.is_hidden;
+                    (Closed::Topic(topic), was_hidden)
+                }
+                ReportTarget::Comment => {
+                    let comment = Comment::find_by_id(&conn, target_id)
+                        .map_err(|_| ErrorCode::CommentNotFound)?;
+                    let topic = comment.get_topic(&conn)?;
+                    let was_hidden = comment.is_hidden;
+                    (Closed::Comment(topic, comment), was_hidden)
+                }
+            };
+            let (board_id, hide_permission, hide_log_type) = match &closed {
+                Closed::Topic(topic) => (topic.board_id, Permission::HideTopic, LogType::HideTopic),
+                Closed::Comment(topic, _) => (
+                    topic.board_id,
+                    Permission::HideComment,
+                    LogType::HideComment,
+                ),
+            };
+            if !permissions.has(Permission::ManageReports, Some(board_id))
+                || (hide_target && !permissions.has(hide_permission, Some(board_id)))
+            {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+
+            let report_count =
+                Report::close_all(&conn, target_type, target_id, status, profile.id)?;
+            if report_count == 0 {
+                return Err(ErrorCode::ReportNotFound.into());
+            }
+
+            let is_hidden = hide_target && !was_hidden;
+            let closed = if is_hidden {
+                let closed = match closed {
+                    Closed::Topic(_) => Closed::Topic(
+                        TopicForm {
+                            id: target_id,
+                            board_id: None,
+                            title: None,
+                            is_closed: None,
+                            is_suspended: None,
+                            is_hidden: Some(true),
+                            is_pinned: None,
+                        }
+                        .save(&conn)?,
+                    ),
+                    Closed::Comment(topic, _) => Closed::Comment(
+                        topic,
+                        CommentForm {
+                            id: target_id,
+                            is_hidden: Some(true),
+                        }
+                        .save(&conn)?,
+                    ),
+                };
+                Log::add(
+                    &conn,
+                    &hide_log_type,
+                    &LogContent {
+                        target: target_id,
+                        ..Default::default()
+                    },
+                    Some(profile.id),
+                    Some(&profile.username),
+                    &ip,
+                )?;
+                closed
+            } else {
+                closed
+            };
+            Log::add(
+                &conn,
+                &log_type,
+                &LogContent {
+                    target: target_id,
+                    before: None,
+                    after: Some(serde_json::json!({
+                        "target_type": target_type,
+                        "report_count": report_count,
+                        "hide_target": is_hidden,
+                    })),
+                },
+                Some(profile.id),
+                Some(&profile.username),
+                &ip,
+            )?;
+            Ok((closed, is_hidden))
+        })
+    })
+    .await?;
+    if is_hidden {
+        match &closed {
+            Closed::Topic(topic) => EVENTS.publish(Event::topic_status(topic)),
+            Closed::Comment(topic, comment) => {
+                EVENTS.publish(Event::comment_status(topic, comment))
+            }
+        }
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn scope() -> Scope {
+    web::scope("/reports")
+        .service(get_reports)
+        .service(put_report_status)
+}
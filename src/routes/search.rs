@@ -0,0 +1,85 @@
+use crate::custom_error::{CustomError, ErrorCode};
+use crate::db::DbPool;
+use crate::models::{
+    highlight, parse_terms, to_boolean_query, Comment, CommentSearchResult, SearchFilter, Topic,
+    TopicSearchResult,
+};
+use actix_web::{
+    get, web,
+    web::{block, Data, Query},
+    HttpResponse, Scope,
+};
+use chrono::{DateTime, Utc};
+
+#[derive(Deserialize, Debug)]
+struct SearchQuery {
+    q: String,
+    board_id: Option<i32>,
+    author_id: Option<i32>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<i32>,
+    offset: Option<i32>,
+}
+
+#[derive(Serialize, Debug)]
+struct SearchResponse {
+    topics: Vec<TopicSearchResult>,
+    comments: Vec<CommentSearchResult>,
+}
+
+#[get("")]
+async fn search(
+    pool: Data<DbPool>,
+    query: Query<SearchQuery>,
+) -> Result<HttpResponse, CustomError> {
+    let terms = parse_terms(&query.q);
+    if terms.is_empty() {
+        return Err(ErrorCode::EmptyQuery.into());
+    }
+    let boolean_query = to_boolean_query(&terms);
+
+    let limit = query.limit.unwrap_or(10);
+    let limit = if limit > 20 { 20 } else { limit };
+    let offset = query.offset.unwrap_or(0);
+    let filter = SearchFilter {
+        board_id: query.board_id,
+        author_id: query.author_id,
+        from: query.from.map(|x| x.naive_utc()),
+        to: query.to.map(|x| x.naive_utc()),
+    };
+
+    let conn = pool.get()?;
+    let (topics, comments) = block(move || {
+        let topics = Topic::search(&conn, &boolean_query, &filter, limit, offset)?;
+        let (comments, comment_topics): (Vec<Comment>, Vec<Topic>) =
+            Comment::search(&conn, &boolean_query, &filter, limit, offset)?
+                .into_iter()
+                .unzip();
+        let comments_public = Comment::get_public_list(&conn, &comments, false)?;
+        let comments = comments
+            .iter()
+            .zip(comments_public)
+            .zip(comment_topics)
+            .map(|((comment, public), topic)| CommentSearchResult {
+                comment: public,
+                topic: topic.get_public(false),
+                snippet: highlight(&comment.content, &terms),
+            })
+            .collect::<Vec<CommentSearchResult>>();
+        let topics = topics
+            .iter()
+            .map(|x| TopicSearchResult {
+                topic: x.get_public(false),
+                snippet: highlight(&x.title, &terms),
+            })
+            .collect::<Vec<TopicSearchResult>>();
+        Ok::<_, anyhow::Error>((topics, comments))
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(SearchResponse { topics, comments }))
+}
+
+pub fn scope() -> Scope {
+    web::scope("/search").service(search)
+}
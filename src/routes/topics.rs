@@ -2,42 +2,157 @@ use std::net::IpAddr;
 
 use crate::auth::{Profile, UserInfo};
 use crate::connection_info::ConnectionInfo;
-use crate::custom_error::CustomError;
+use crate::custom_error::{CustomError, ErrorCode};
 use crate::db::DbPool;
+use crate::events::{stream_response, Channel, Event, EVENTS};
 use crate::models::{
-    Board, Comment, CommentPublic, Log, LogContent, LogType, PublicEntity, Topic, TopicForm,
+    ip_from_bytes, Ban, Board, Comment, Feed, Log, LogContent, LogType, Permission, Permissions,
+    PublicEntity, ReportTarget, Topic, TopicForm, TopicWatch, FEED_SIZE,
 };
+use crate::rate_limit::{Action, RATE_LIMITER};
+use crate::routes::reports::{file_report, PostReportRequest};
+use crate::wiki::api_url;
 use actix_web::client::Client;
 use actix_web::{
-    error::BlockingError,
-    get, post, put, web,
+    delete, get, patch, post, put, web,
     web::{block, Data, Path, Query},
     HttpRequest, HttpResponse, Scope,
 };
 use actix_web_validator::Json;
 use anyhow::{anyhow, Result};
-use derive_more::Display;
 use diesel::{Connection, MysqlConnection};
 use validator::Validate;
 
+#[derive(Deserialize, Debug)]
+struct GetTopicQuery {
+    show_hidden: Option<bool>,
+}
+
 #[get("{topic_id}")]
 async fn get_topic(
     pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
     Path((topic_id,)): Path<(i32,)>,
+    query: Query<GetTopicQuery>,
     request: HttpRequest,
 ) -> Result<HttpResponse, CustomError> {
+    let show_hidden = query.show_hidden.unwrap_or(false);
+    let profile = if show_hidden {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
+        }
+    } else {
+        None
+    };
     let conn = pool.get()?;
-    match block(move || Topic::find_by_id(&conn, topic_id)).await {
-        Ok(topic) => {
-            if topic.is_hidden {
-                Ok(HttpResponse::Forbidden().body("Topic is hidden"))
-            } else {
-                Ok(topic.get_public().cache_response(&request))
+    let topic = block(move || -> Result<_, CustomError> {
+        let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+        if let Some(profile) = profile {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ViewHidden, Some(topic.board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
             }
+        } else if topic.is_hidden {
+            return Err(ErrorCode::TopicHidden.into());
         }
-        Err(BlockingError::Error(_)) => Ok(HttpResponse::NotFound().body("Topic is not found")),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
-    }
+        Ok(topic)
+    })
+    .await?;
+    Ok(topic.get_public(show_hidden).cache_response(&request))
+}
+
+/// The raw IP of the author, which is otherwise shown according to `IP_DISPLAY`.
+#[get("{topic_id}/author_ip")]
+async fn get_topic_author_ip(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((topic_id,)): Path<(i32,)>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+    let conn = pool.get()?;
+    let ip = block(move || -> Result<_, CustomError> {
+        let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+        let permissions = Permissions::get(&conn, &profile)?;
+        if !permissions.has(Permission::ViewIp, Some(topic.board_id)) {
+            return Err(ErrorCode::PermissionDenied.into());
+        }
+        Ok(ip_from_bytes(&topic.author_ip))
+    })
+    .await?;
+    Ok(HttpResponse::Ok()
+        .set_header("Cache-Control", "private, no-cache")
+        .json(serde_json::json!({ "ip": ip.to_string() })))
+}
+
+#[get("{topic_id}/events")]
+async fn get_topic_events(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((topic_id,)): Path<(i32,)>,
+    query: Query<GetTopicQuery>,
+    request: HttpRequest,
+) -> Result<HttpResponse, CustomError> {
+    let show_hidden = query.show_hidden.unwrap_or(false);
+    let profile = if show_hidden {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
+        }
+    } else {
+        None
+    };
+    let conn = pool.get()?;
+    block(move || -> Result<_, CustomError> {
+        let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+        if let Some(profile) = profile {
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::ViewHidden, Some(topic.board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+        } else if topic.is_hidden {
+            return Err(ErrorCode::TopicHidden.into());
+        }
+        Ok(())
+    })
+    .await?;
+    Ok(stream_response(
+        &request,
+        Channel::Topic(topic_id),
+        show_hidden,
+    ))
+}
+
+#[get("{topic_id}/feed.atom")]
+async fn get_topic_feed(
+    pool: Data<DbPool>,
+    Path((topic_id,)): Path<(i32,)>,
+    request: HttpRequest,
+) -> Result<HttpResponse, CustomError> {
+    let conn = pool.get()?;
+    let feed = block(move || -> Result<_, CustomError> {
+        let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+        if topic.is_hidden {
+            return Err(ErrorCode::TopicHidden.into());
+        }
+        let offset = (topic.comment_count - FEED_SIZE).max(0);
+        let comments = topic
+            .get_comments(&conn, FEED_SIZE, offset)?
+            .into_iter()
+            .filter(|x| !x.is_hidden)
+            .collect::<Vec<Comment>>();
+        let comments = Comment::get_public_list(&conn, &comments, false)?;
+        Ok(Feed::from_topic(&topic.get_public(false), &comments))
+    })
+    .await?;
+    Ok(feed.cache_response_with(
+        &request,
+        "application/atom+xml; charset=utf-8",
+        Feed::to_atom,
+    ))
 }
 
 #[derive(Deserialize, Validate, Debug)]
@@ -94,7 +209,10 @@ fn log_put_topic_status(
     Log::add(
         &conn,
         &log_type,
-        &LogContent { target: topic_id },
+        &LogContent {
+            target: topic_id,
+            ..Default::default()
+        },
         user_id,
         user_name,
         &user_ip,
@@ -110,40 +228,37 @@ async fn put_topic_status(
     Json(req_status): Json<PutTopicStatusRequest>,
     ConnectionInfo { ip }: ConnectionInfo,
 ) -> Result<HttpResponse, CustomError> {
-    #[derive(Debug, Display)]
-    enum ErrorKind {
-        TopicNotFound,
-        OtherError(anyhow::Error),
-    }
-    impl From<diesel::result::Error> for ErrorKind {
-        fn from(error: diesel::result::Error) -> Self {
-            ErrorKind::OtherError(error.into())
-        }
-    }
-
     let profile = match token {
         Some(token) => Profile::get(&token).await?,
-        None => return Ok(HttpResponse::Unauthorized().body("TokenMissing")),
+        None => return Err(ErrorCode::TokenMissing.into()),
     };
 
-    if !profile.is_admin() {
-        return Ok(HttpResponse::Forbidden().finish());
-    }
-
     let conn = pool.get()?;
-    let res = block(move || {
-        conn.transaction::<Topic, _, _>(|| {
-            Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorKind::TopicNotFound)?;
+    let topic = block(move || {
+        conn.transaction::<Topic, CustomError, _>(|| {
+            let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+            let permissions = Permissions::get(&conn, &profile)?;
+            let requested = [
+                (req_status.is_closed, Permission::CloseTopic),
+                (req_status.is_suspended, Permission::SuspendTopic),
+                (req_status.is_hidden, Permission::HideTopic),
+                (req_status.is_pinned, Permission::PinTopic),
+            ];
+            if requested.iter().any(|(value, permission)| {
+                value.is_some() && !permissions.has(*permission, Some(topic.board_id))
+            }) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
             let topic_changes = TopicForm {
                 id: topic_id,
+                board_id: None,
+                title: None,
                 is_closed: req_status.is_closed,
                 is_suspended: req_status.is_suspended,
                 is_hidden: req_status.is_hidden,
                 is_pinned: req_status.is_pinned,
             };
-            let changed = topic_changes
-                .save(&conn)
-                .map_err(|e| ErrorKind::OtherError(e))?;
+            let changed = topic_changes.save(&conn)?;
             log_put_topic_status(
                 &conn,
                 topic_id,
@@ -151,79 +266,177 @@ async fn put_topic_status(
                 Some(&profile.username),
                 &ip,
                 req_status,
-            )
-            .map_err(|e| ErrorKind::OtherError(e))?;
+            )?;
             Ok(changed)
         })
     })
-    .await;
+    .await?;
+    EVENTS.publish(Event::topic_status(&topic));
+    Ok(HttpResponse::Ok().json(topic.get_public(false)))
+}
 
-    match res {
-        Ok(topic) => Ok(HttpResponse::Ok().json(topic.get_public())),
-        Err(BlockingError::Error(ErrorKind::TopicNotFound)) => {
-            Ok(HttpResponse::NotFound().body("Topic is not found"))
-        }
-        Err(BlockingError::Error(ErrorKind::OtherError(e))) => Err(e.into()),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
+#[derive(Deserialize, Validate, Debug)]
+struct PatchTopicRequest {
+    board_id: Option<i32>,
+    #[validate(length(min = 1, max = 100))]
+    title: Option<String>,
+}
+
+#[patch("{topic_id}")]
+async fn patch_topic(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((topic_id,)): Path<(i32,)>,
+    Json(PatchTopicRequest { board_id, title }): Json<PatchTopicRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    if board_id.is_none() && title.is_none() {
+        return Err(ErrorCode::NothingToChange.into());
     }
+
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    let topic = block(move || {
+        conn.transaction::<Topic, CustomError, _>(|| {
+            let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+            let permissions = Permissions::get(&conn, &profile)?;
+            if !permissions.has(Permission::EditTopic, Some(topic.board_id)) {
+                return Err(ErrorCode::PermissionDenied.into());
+            }
+            let board_id = board_id.filter(|x| *x != topic.board_id);
+            let title = title.filter(|x| *x != topic.title);
+            let board = match board_id {
+                Some(board_id) => {
+                    Some(Board::find_by_id(&conn, board_id).map_err(|_| ErrorCode::BoardNotFound)?)
+                }
+                None => None,
+            };
+            if let Some(board) = &board {
+                if !permissions.has(Permission::EditTopic, Some(board.id)) {
+                    return Err(ErrorCode::PermissionDenied.into());
+                }
+                if !board.is_active {
+                    return Err(ErrorCode::BoardInactive.into());
+                }
+            }
+            if board_id.is_none() && title.is_none() {
+                return Ok(topic);
+            }
+            let topic_changes = TopicForm {
+                id: topic_id,
+                board_id,
+                title: title.clone(),
+                is_closed: None,
+                is_suspended: None,
+                is_hidden: None,
+                is_pinned: None,
+            };
+            let mut changed = topic_changes.save(&conn)?;
+            match &board {
+                Some(board) if board.is_anonymous && changed.anonymous_name.is_none() => {
+                    board.fill_pseudonyms(&conn)?;
+                    changed = Topic::find_by_id(&conn, topic_id)?;
+                }
+                _ => {}
+            }
+            if let Some(title) = title {
+                Log::add(
+                    &conn,
+                    &LogType::RenameTopic,
+                    &LogContent {
+                        target: topic_id,
+                        before: Some(topic.title.into()),
+                        after: Some(title.into()),
+                    },
+                    Some(profile.id),
+                    Some(&profile.username),
+                    &ip,
+                )?;
+            }
+            if let Some(board_id) = board_id {
+                Log::add(
+                    &conn,
+                    &LogType::MoveTopic,
+                    &LogContent {
+                        target: topic_id,
+                        before: Some(topic.board_id.into()),
+                        after: Some(board_id.into()),
+                    },
+                    Some(profile.id),
+                    Some(&profile.username),
+                    &ip,
+                )?;
+            }
+            Ok(changed)
+        })
+    })
+    .await?;
+    Ok(HttpResponse::Ok().json(topic.get_public(false)))
 }
 
 #[derive(Deserialize, Debug)]
 struct GetCommentsQuery {
     limit: Option<i32>,
     offset: Option<i32>,
+    /// Returns `limit` comments on each side of this comment instead of a page.
+    around: Option<i32>,
+    show_hidden: Option<bool>,
 }
 
 #[get("{topic_id}/comments")]
 async fn get_topic_comments(
     pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
     Path((topic_id,)): Path<(i32,)>,
     query: Query<GetCommentsQuery>,
     request: HttpRequest,
 ) -> Result<HttpResponse, CustomError> {
-    #[derive(Debug, Display)]
-    enum ErrorKind {
-        TopicNotFound,
-        TopicIsHidden,
-        OtherError(anyhow::Error),
-    }
-
     let limit = query.limit.unwrap_or(10);
     let limit = if limit > 20 { 20 } else { limit };
     let offset = query.offset.unwrap_or(0);
+    let around = query.around;
+    let show_hidden = query.show_hidden.unwrap_or(false);
+    let profile = if show_hidden {
+        match token {
+            Some(token) => Some(Profile::get(&token).await?),
+            None => return Err(ErrorCode::TokenMissing.into()),
+        }
+    } else {
+        None
+    };
 
     let conn = pool.get()?;
-    let res = block(move || {
+    let comments = block(move || -> Result<_, CustomError> {
         if let Ok(topic) = Topic::find_by_id(&conn, topic_id) {
-            if topic.is_hidden {
-                return Err(ErrorKind::TopicIsHidden);
+            if let Some(profile) = profile {
+                let permissions = Permissions::get(&conn, &profile)?;
+                if !permissions.has(Permission::ViewHidden, Some(topic.board_id)) {
+                    return Err(ErrorCode::PermissionDenied.into());
+                }
+            } else if topic.is_hidden {
+                return Err(ErrorCode::TopicHidden.into());
             }
-            let comments = topic
-                .get_comments(&conn, limit, offset)
-                .map_err(|e| ErrorKind::OtherError(e))?;
-            Ok(comments)
+            let comments = match around {
+                Some(comment_id) => {
+                    match Comment::find_by_id(&conn, comment_id) {
+                        Ok(comment) if comment.topic_id == topic.id => {}
+                        _ => return Err(ErrorCode::CommentNotFound.into()),
+                    }
+                    topic.get_comments_around(&conn, comment_id, limit)?
+                }
+                None => topic.get_comments(&conn, limit, offset)?,
+            };
+            Ok(Comment::get_public_list(&conn, &comments, show_hidden)?)
         } else {
-            Err(ErrorKind::TopicNotFound)
+            Err(ErrorCode::TopicNotFound.into())
         }
     })
-    .await;
-    match res {
-        Ok(comments) => {
-            let comments = comments
-                .iter()
-                .map(|x| x.get_public(false))
-                .collect::<Vec<CommentPublic>>();
-            Ok(comments.cache_response(&request))
-        }
-        Err(BlockingError::Error(ErrorKind::TopicNotFound)) => {
-            Ok(HttpResponse::NotFound().body("Topic is not found"))
-        }
-        Err(BlockingError::Error(ErrorKind::TopicIsHidden)) => {
-            Ok(HttpResponse::Forbidden().body("Topic is hidden"))
-        }
-        Err(BlockingError::Error(ErrorKind::OtherError(e))) => Err(e.into()),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
-    }
+    .await?;
+    Ok(comments.cache_response(&request))
 }
 
 #[derive(Deserialize, Validate, Debug)]
@@ -254,7 +467,8 @@ async fn is_blocked_ip(ip: &IpAddr) -> Result<bool> {
     let client = Client::default();
     let mut res = client
         .get(format!(
-            "https://librewiki.net/api.php?action=query&list=blocks&bkip={}&format=json",
+            "{}?action=query&list=blocks&bkip={}&format=json",
+            api_url(),
             ip
         ))
         .set_header("Accept", "application/json")
@@ -278,140 +492,240 @@ async fn post_topic(
     UserInfo { token, .. }: UserInfo,
     pool: Data<DbPool>,
 ) -> Result<HttpResponse, CustomError> {
-    #[derive(Debug, Display)]
-    enum ErrorKind {
-        BoardNotFound,
-        OtherError(anyhow::Error),
-    }
-
     let profile = match token {
         Some(token) => Some(Profile::get(&token).await?),
         None => None,
     };
 
+    let rate_limit = RATE_LIMITER.check(Action::PostTopic, profile.as_ref().map(|x| x.id), ip);
+    if !rate_limit.allowed {
+        return Ok(rate_limit.throttled_response());
+    }
+
+    let user_id = profile.as_ref().map(|x| x.id);
+    let conn = pool.get()?;
+    if block(move || Ban::find_active(&conn, user_id, &ip))
+        .await?
+        .is_some()
+    {
+        return Err(ErrorCode::UserBlocked.into());
+    }
+
     if let Some(profile) = &profile {
         if profile.blocked {
-            return Ok(HttpResponse::Forbidden().body("You are blocked"));
+            return Err(ErrorCode::UserBlocked.into());
         }
     } else if is_blocked_ip(&ip).await? {
-        return Ok(HttpResponse::Forbidden().body("You are blocked"));
+        return Err(ErrorCode::UserBlocked.into());
     }
 
     let conn = pool.get()?;
-    let res = block::<_, Topic, ErrorKind>(move || {
-        let board = Board::find_by_id(&conn, board_id).map_err(|_| ErrorKind::BoardNotFound)?;
-        let topic = conn
-            .transaction::<Topic, _, _>(|| match profile {
-                Some(Profile { id, username, .. }) => {
-                    Topic::create(&conn, &board, &title, Some(id), Some(&username), &ip)?;
-                    let topic = Topic::get_latest(&conn)?;
-                    Comment::create(&conn, &topic, &content, Some(id), Some(&username), &ip)?;
-                    Ok(topic)
-                }
-                None => {
-                    Topic::create(&conn, &board, &title, None, None, &ip)?;
-                    let topic = Topic::get_latest(&conn)?;
-                    Comment::create(&conn, &topic, &content, None, None, &ip)?;
-                    Ok(topic)
-                }
-            })
-            .map_err(|e| ErrorKind::OtherError(e))?;
+    let topic = block(move || -> Result<Topic, CustomError> {
+        let board = Board::find_by_id(&conn, board_id).map_err(|_| ErrorCode::BoardNotFound)?;
+        if !board.is_active {
+            return Err(ErrorCode::BoardInactive.into());
+        }
+        let topic = conn.transaction::<Topic, CustomError, _>(|| match profile {
+            Some(Profile { id, username, .. }) => {
+                let topic = Topic::create(&conn, &board, &title, Some(id), Some(&username), &ip)?;
+                Comment::create(
+                    &conn,
+                    &topic,
+                    None,
+                    &content,
+                    Some(id),
+                    Some(&username),
+                    &ip,
+                )?;
+                Ok(topic)
+            }
+            None => {
+                let topic = Topic::create(&conn, &board, &title, None, None, &ip)?;
+                Comment::create(&conn, &topic, None, &content, None, None, &ip)?;
+                Ok(topic)
+            }
+        })?;
+        EVENTS.publish(Event::new_topic(&topic));
         Ok(topic)
     })
-    .await;
-    match res {
-        Ok(topic) => Ok(HttpResponse::Ok().json(topic.get_public())),
-        Err(BlockingError::Error(ErrorKind::BoardNotFound)) => {
-            Ok(HttpResponse::NotFound().body("Board is not found"))
-        }
-        Err(BlockingError::Error(ErrorKind::OtherError(e))) => Err(e.into()),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
-    }
+    .await?;
+    Ok(HttpResponse::Ok().json(topic.get_public(false)))
 }
 
 #[derive(Deserialize, Validate, Debug)]
 struct PostCommentRequest {
     #[validate(length(min = 1, max = 100000))]
     content: String,
+    parent_id: Option<i32>,
 }
 
 #[post("{topic_id}/comments")]
 async fn post_topic_comments(
     ConnectionInfo { ip }: ConnectionInfo,
-    Json(PostCommentRequest { content }): Json<PostCommentRequest>,
+    Json(PostCommentRequest { content, parent_id }): Json<PostCommentRequest>,
     Path((topic_id,)): Path<(i32,)>,
     UserInfo { token, .. }: UserInfo,
     pool: Data<DbPool>,
 ) -> Result<HttpResponse, CustomError> {
-    #[derive(Debug, Display)]
-    enum ErrorKind {
-        TopicNotFound,
-        TopicIsHidden,
-        TopicIsSuspended,
-        TopicIsClosed,
-        OtherError(anyhow::Error),
-    }
-
     let profile = match token {
         Some(token) => Some(Profile::get(&token).await?),
         None => None,
     };
 
+    let rate_limit = RATE_LIMITER.check(Action::PostComment, profile.as_ref().map(|x| x.id), ip);
+    if !rate_limit.allowed {
+        return Ok(rate_limit.throttled_response());
+    }
+
+    let user_id = profile.as_ref().map(|x| x.id);
+    let conn = pool.get()?;
+    if block(move || Ban::find_active(&conn, user_id, &ip))
+        .await?
+        .is_some()
+    {
+        return Err(ErrorCode::UserBlocked.into());
+    }
+
     if let Some(profile) = &profile {
         if profile.blocked {
-            return Ok(HttpResponse::Forbidden().body("You are blocked"));
+            return Err(ErrorCode::UserBlocked.into());
         }
     } else if is_blocked_ip(&ip).await? {
-        return Ok(HttpResponse::Forbidden().body("You are blocked"));
+        return Err(ErrorCode::UserBlocked.into());
     }
 
     let conn = pool.get()?;
-    let res = block::<_, (), ErrorKind>(move || {
-        let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorKind::TopicNotFound)?;
-        if topic.is_hidden {
-            return Err(ErrorKind::TopicIsHidden);
-        } else if topic.is_closed {
-            return Err(ErrorKind::TopicIsClosed);
-        } else if topic.is_suspended {
-            return Err(ErrorKind::TopicIsSuspended);
-        }
-        match profile {
-            Some(Profile { id, username, .. }) => {
-                Comment::create(&conn, &topic, &content, Some(id), Some(&username), &ip)
-                    .map_err(|e| ErrorKind::OtherError(e))?;
+    let comment = block(move || -> Result<Comment, CustomError> {
+        let (topic, comment) = conn.transaction::<_, CustomError, _>(|| {
+            let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+            if topic.is_hidden {
+                return Err(ErrorCode::TopicHidden.into());
+            } else if topic.is_closed {
+                return Err(ErrorCode::TopicClosed.into());
+            } else if topic.is_suspended {
+                return Err(ErrorCode::TopicSuspended.into());
             }
-            None => {
-                Comment::create(&conn, &topic, &content, None, None, &ip)
-                    .map_err(|e| ErrorKind::OtherError(e))?;
+            let board = topic.get_board(&conn)?;
+            if !board.is_active {
+                return Err(ErrorCode::BoardInactive.into());
             }
-        };
-        Ok(())
+            let parent = match parent_id {
+                Some(parent_id) => match Comment::find_by_id(&conn, parent_id) {
+                    Ok(parent) if parent.topic_id == topic.id => Some(parent),
+                    _ => return Err(ErrorCode::InvalidParent.into()),
+                },
+                None => None,
+            };
+            let comment = match profile {
+                Some(Profile { id, username, .. }) => Comment::create(
+                    &conn,
+                    &topic,
+                    parent.as_ref(),
+                    &content,
+                    Some(id),
+                    Some(&username),
+                    &ip,
+                )?,
+                None => Comment::create(&conn, &topic, parent.as_ref(), &content, None, None, &ip)?,
+            };
+            Ok((topic, comment))
+        })?;
+        EVENTS.publish(Event::new_comment(&topic, &comment));
+        Ok(comment)
     })
-    .await;
-    match res {
-        Ok(()) => Ok(HttpResponse::NoContent().finish()),
-        Err(BlockingError::Error(ErrorKind::TopicNotFound)) => {
-            Ok(HttpResponse::NotFound().body("Topic is not found"))
-        }
-        Err(BlockingError::Error(ErrorKind::TopicIsHidden)) => {
-            Ok(HttpResponse::Forbidden().body("Topic is hidden"))
-        }
-        Err(BlockingError::Error(ErrorKind::TopicIsClosed)) => {
-            Ok(HttpResponse::Forbidden().body("Topic is closed"))
-        }
-        Err(BlockingError::Error(ErrorKind::TopicIsSuspended)) => {
-            Ok(HttpResponse::Forbidden().body("Topic is suspended"))
+    .await?;
+    Ok(HttpResponse::Ok().json(comment.get_public(false)))
+}
+
+#[put("{topic_id}/watch")]
+async fn put_topic_watch(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((topic_id,)): Path<(i32,)>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    block(move || -> Result<_, CustomError> {
+        let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+        if topic.is_hidden {
+            return Err(ErrorCode::TopicHidden.into());
         }
-        Err(BlockingError::Error(ErrorKind::OtherError(e))) => Err(e.into()),
-        Err(BlockingError::Canceled) => Err(BlockingError::Canceled.into()),
+        TopicWatch::add(&conn, topic.id, profile.id)?;
+        Ok(())
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("{topic_id}/watch")]
+async fn delete_topic_watch(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((topic_id,)): Path<(i32,)>,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Profile::get(&token).await?,
+        None => return Err(ErrorCode::TokenMissing.into()),
+    };
+
+    let conn = pool.get()?;
+    block(move || TopicWatch::remove(&conn, topic_id, profile.id)).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("{topic_id}/reports")]
+async fn post_topic_report(
+    pool: Data<DbPool>,
+    UserInfo { token, .. }: UserInfo,
+    Path((topic_id,)): Path<(i32,)>,
+    Json(request): Json<PostReportRequest>,
+    ConnectionInfo { ip }: ConnectionInfo,
+) -> Result<HttpResponse, CustomError> {
+    let profile = match token {
+        Some(token) => Some(Profile::get(&token).await?),
+        None => None,
+    };
+
+    let rate_limit = RATE_LIMITER.check(Action::Report, profile.as_ref().map(|x| x.id), ip);
+    if !rate_limit.allowed {
+        return Ok(rate_limit.throttled_response());
     }
+
+    let conn = pool.get()?;
+    block(move || -> Result<_, CustomError> {
+        let topic = Topic::find_by_id(&conn, topic_id).map_err(|_| ErrorCode::TopicNotFound)?;
+        if topic.is_hidden {
+            return Err(ErrorCode::TopicHidden.into());
+        }
+        file_report(
+            &conn,
+            ReportTarget::Topic,
+            topic.id,
+            request,
+            profile.as_ref(),
+            &ip,
+        )
+    })
+    .await?;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 pub fn scope() -> Scope {
     web::scope("/topics")
         .service(post_topic)
         .service(get_topic)
+        .service(get_topic_author_ip)
+        .service(post_topic_report)
+        .service(get_topic_events)
+        .service(get_topic_feed)
+        .service(patch_topic)
         .service(put_topic_status)
         .service(get_topic_comments)
         .service(post_topic_comments)
+        .service(put_topic_watch)
+        .service(delete_topic_watch)
 }
@@ -1,23 +1,77 @@
+table! {
+    bans (id) {
+        id -> Integer,
+        ip_start -> Nullable<Varbinary>,
+        ip_end -> Nullable<Varbinary>,
+        prefix_length -> Nullable<Integer>,
+        user_id -> Nullable<Integer>,
+        reason -> Varchar,
+        expires_at -> Nullable<Timestamp>,
+        author_id -> Nullable<Integer>,
+        author_name -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     boards (id) {
         id -> Integer,
         display_name -> Varchar,
         name -> Varchar,
         is_active -> Bool,
+        allow_anonymous_reactions -> Bool,
+        is_anonymous -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
     }
 }
 
+table! {
+    comment_mentions (id) {
+        id -> Integer,
+        comment_id -> Integer,
+        user_id -> Integer,
+        user_name -> Varchar,
+        start_offset -> Integer,
+        end_offset -> Integer,
+    }
+}
+
+table! {
+    comment_reactions (id) {
+        id -> Integer,
+        comment_id -> Integer,
+        emoji -> Varchar,
+        user_id -> Nullable<Integer>,
+        user_ip -> Varbinary,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    comment_revisions (id) {
+        id -> Integer,
+        comment_id -> Integer,
+        content -> Mediumtext,
+        user_id -> Nullable<Integer>,
+        user_name -> Nullable<Varchar>,
+        user_ip -> Varbinary,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     comments (id) {
         id -> Integer,
         topic_id -> Integer,
+        parent_id -> Nullable<Integer>,
         content -> Mediumtext,
         author_id -> Nullable<Integer>,
         author_name -> Nullable<Varchar>,
         author_ip -> Varbinary,
+        anonymous_name -> Nullable<Varchar>,
         is_hidden -> Bool,
+        edited_at -> Nullable<Timestamp>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
     }
@@ -42,6 +96,55 @@ table! {
     }
 }
 
+table! {
+    notifications (id) {
+        id -> Integer,
+        user_id -> Integer,
+        kind -> Varchar,
+        topic_id -> Integer,
+        comment_id -> Integer,
+        is_read -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    permission_grants (id) {
+        id -> Integer,
+        permission -> Varchar,
+        wiki_group -> Nullable<Varchar>,
+        wiki_right -> Nullable<Varchar>,
+        board_id -> Nullable<Integer>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    reports (id) {
+        id -> Integer,
+        target_type -> Varchar,
+        target_id -> Integer,
+        reason -> Varchar,
+        content -> Varchar,
+        user_id -> Nullable<Integer>,
+        user_name -> Nullable<Varchar>,
+        user_ip -> Varbinary,
+        status -> Varchar,
+        resolved_by -> Nullable<Integer>,
+        resolved_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    topic_watches (id) {
+        id -> Integer,
+        topic_id -> Integer,
+        user_id -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     topics (id) {
         id -> Integer,
@@ -50,6 +153,7 @@ table! {
         author_id -> Nullable<Integer>,
         author_name -> Nullable<Varchar>,
         author_ip -> Varbinary,
+        anonymous_name -> Nullable<Varchar>,
         is_closed -> Bool,
         is_suspended -> Bool,
         is_hidden -> Bool,
@@ -60,14 +164,29 @@ table! {
     }
 }
 
+joinable!(comment_mentions -> comments (comment_id));
+joinable!(comment_reactions -> comments (comment_id));
+joinable!(comment_revisions -> comments (comment_id));
 joinable!(comments -> topics (topic_id));
 joinable!(logs -> log_types (log_type_id));
+joinable!(notifications -> comments (comment_id));
+joinable!(notifications -> topics (topic_id));
+joinable!(permission_grants -> boards (board_id));
+joinable!(topic_watches -> topics (topic_id));
 joinable!(topics -> boards (board_id));
 
 allow_tables_to_appear_in_same_query!(
+    bans,
     boards,
+    comment_mentions,
+    comment_reactions,
+    comment_revisions,
     comments,
     logs,
     log_types,
+    notifications,
+    permission_grants,
+    reports,
+    topic_watches,
     topics,
 );
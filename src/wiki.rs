@@ -0,0 +1,260 @@
+use crate::markdown::RENDER_CACHE;
+use actix_web::client::Client;
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Base URL of the wiki, without a trailing slash.
+    pub static ref WIKI_URL: String = env::var("WIKI_URL")
+        .unwrap_or_else(|_| "https://librewiki.net".to_owned())
+        .trim_end_matches('/')
+        .to_owned();
+    pub static ref PAGE_CACHE: PageCache = PageCache::new(
+        env::var("WIKI_RED_LINKS").is_ok_and(|x| x == "true" || x == "1"),
+        Duration::from_secs(
+            env::var("WIKI_PAGE_CACHE_TTL")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(3600)
+        ),
+    );
+}
+
+/// The MediaWiki API accepts this many titles per query.
+const TITLES_PER_QUERY: usize = 50;
+
+/// Pending titles are looked up this often.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+pub fn api_url() -> String {
+    format!("{}/api.php", *WIKI_URL)
+}
+
+pub fn rest_url(path: &str) -> String {
+    format!("{}/rest.php{}", *WIKI_URL, path)
+}
+
+/// Normalizes a page title the way MediaWiki does for most namespaces:
+/// underscores are spaces, runs of spaces are collapsed and the first letter is capitalized.
+pub fn normalize_title(title: &str) -> String {
+    let title = title
+        .replace('_', " ")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ");
+    let mut chars = title.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => title,
+    }
+}
+
+/// Percent-encodes a title like MediaWiki's `wfUrlencode`, with spaces as underscores.
+fn encode_title(title: &str) -> String {
+    let mut out = String::new();
+    for b in title.replace(' ', "_").bytes() {
+        match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'~'
+            | b';'
+            | b':'
+            | b'@'
+            | b'$'
+            | b'!'
+            | b'*'
+            | b'('
+            | b')'
+            | b','
+            | b'/' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+/// Returns the URL of a wiki page, optionally with a section anchor.
+pub fn page_url(title: &str, fragment: Option<&str>) -> String {
+    let mut url = format!(
+        "{}/wiki/{}",
+        *WIKI_URL,
+        encode_title(&normalize_title(title))
+    );
+    if let Some(fragment) = fragment {
+        url.push('#');
+        url.push_str(&encode_title(fragment.trim()));
+    }
+    url
+}
+
+/// Remembers which wiki pages exist, so that links to missing pages can be shown as red links.
+/// Lookups never wait for the wiki. Unknown titles are queued and looked up in the background.
+pub struct PageCache {
+    enabled: bool,
+    ttl: Duration,
+    pages: RwLock<HashMap<String, (bool, Instant)>>,
+    pending: Mutex<HashSet<String>>,
+}
+
+impl PageCache {
+    pub fn new(enabled: bool, ttl: Duration) -> Self {
+        PageCache {
+            enabled,
+            ttl,
+            pages: RwLock::new(HashMap::new()),
+            pending: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns whether a page exists, or `None` if it is not known yet.
+    pub fn exists(&self, title: &str) -> Option<bool> {
+        if !self.enabled {
+            return None;
+        }
+        let title = normalize_title(title);
+        if let Some((exists, fetched_at)) = self.pages.read().unwrap().get(&title) {
+            if fetched_at.elapsed() < self.ttl {
+                return Some(*exists);
+            }
+        }
+        self.pending.lock().unwrap().insert(title);
+        None
+    }
+
+    fn update(&self, results: Vec<(String, bool)>) -> bool {
+        let mut pages = self.pages.write().unwrap();
+        let mut changed = false;
+        for (title, exists) in results {
+            let previous = pages.insert(title, (exists, Instant::now()));
+            changed = changed || previous.map(|(x, _)| x) != Some(exists);
+        }
+        changed
+    }
+
+    async fn fetch(titles: &[String]) -> anyhow::Result<Vec<(String, bool)>> {
+        let client = Client::default();
+        let mut res = client
+            .get(api_url())
+            .query(&[
+                ("action", "query"),
+                ("format", "json"),
+                ("titles", &titles.join("|")),
+            ])?
+            .set_header("Accept", "application/json")
+            .send()
+            .await
+            .map_err(|e| anyhow!(format!("{}", e)))?;
+        let data: serde_json::Value = res.json().await?;
+        let query = &data["query"];
+        let normalized = query["normalized"]
+            .as_array()
+            .map(|x| {
+                x.iter()
+                    .filter_map(|x| Some((x["from"].as_str()?, x["to"].as_str()?)))
+                    .collect::<HashMap<&str, &str>>()
+            })
+            .unwrap_or_default();
+        let existing = query["pages"]
+            .as_object()
+            .map(|x| {
+                x.values()
+                    .filter(|x| x.get("missing").is_none() && x.get("invalid").is_none())
+                    .filter_map(|x| x["title"].as_str())
+                    .collect::<HashSet<&str>>()
+            })
+            .unwrap_or_default();
+        Ok(titles
+            .iter()
+            .map(|x| {
+                let title = normalized.get(x.as_str()).copied().unwrap_or(x);
+                (x.clone(), existing.contains(title))
+            })
+            .collect())
+    }
+
+    /// Looks up the pending titles. Rendered contents are dropped if any page changed.
+    pub async fn refresh(&self) -> anyhow::Result<()> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .drain()
+            .collect::<Vec<String>>();
+        let mut changed = false;
+        for titles in pending.chunks(TITLES_PER_QUERY) {
+            let results = Self::fetch(titles).await?;
+            changed = self.update(results) || changed;
+        }
+        if changed {
+            RENDER_CACHE.clear();
+        }
+        Ok(())
+    }
+
+    /// Spawns the background lookup on the current arbiter, if red links are enabled.
+    pub fn start_refresh(&'static self) {
+        if !self.enabled {
+            return;
+        }
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(REFRESH_INTERVAL);
+            loop {
+                interval.tick().await;
+                // Titles that failed are queued again when they are next rendered
+                let _ = self.refresh().await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_url() {
+        assert_eq!("Foo bar", normalize_title(" foo__bar "));
+        assert_eq!(
+            format!(
+                "{}/wiki/%EB%A6%AC%EB%B8%8C%EB%A0%88_%EC%9C%84%ED%82%A4",
+                *WIKI_URL
+            ),
+            page_url("리브레 위키", None)
+        );
+        assert_eq!(
+            format!("{}/wiki/Help:A%26B#Section_1", *WIKI_URL),
+            page_url("help:A&B", Some(" Section 1"))
+        );
+    }
+
+    #[test]
+    fn test_page_cache() {
+        let cache = PageCache::new(true, Duration::from_secs(60));
+        assert_eq!(None, cache.exists("foo"));
+        assert_eq!(
+            vec!["Foo".to_owned()],
+            cache
+                .pending
+                .lock()
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(true, cache.update(vec![("Foo".to_owned(), false)]));
+        assert_eq!(Some(false), cache.exists("foo"));
+        assert_eq!(false, cache.update(vec![("Foo".to_owned(), false)]));
+
+        let disabled = PageCache::new(false, Duration::from_secs(60));
+        assert_eq!(None, disabled.exists("foo"));
+        assert_eq!(0, disabled.pending.lock().unwrap().len());
+    }
+}